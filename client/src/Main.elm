@@ -428,7 +428,7 @@ viewLoggedIn { serverMessage, answer, lastQuestion } =
                     [ p [ class "w-[90vw] text-5xl mb-10 text-white break-words text-center" ] [ text question ]
                     , div [ class "w-[80%] flex flex-col gap-4 mx-auto" ]
                         (options
-                            |> zip [ "One", "Two", "Three", "Four" ]
+                            |> zip optionIndices
                             |> List.map
                                 (\( idx, opt ) ->
                                     button
@@ -469,7 +469,7 @@ viewLoggedIn { serverMessage, answer, lastQuestion } =
                     [ p [ class "w-[90vw] text-5xl mb-10 text-white break-words text-center" ] [ text q.question ]
                     , div [ class "w-[80%] flex flex-col gap-4 mx-auto" ]
                         (q.options
-                            |> zip [ "One", "Two", "Three", "Four" ]
+                            |> zip optionIndices
                             |> List.map
                                 (\( idx, opt ) ->
                                     button
@@ -535,3 +535,8 @@ viewLoggedOut { username, password } =
                 [ text "Register" ]
             ]
         ]
+
+
+optionIndices : List String
+optionIndices =
+    [ "One", "Two", "Three", "Four", "Five", "Six" ]
@@ -1,6 +1,8 @@
 use crate::{
-    models::{AnswerStatus, Game, OptionIndex, Question},
-    ports::{GameDatabase, UserModel, UsersDatabase},
+    models::{
+        Answer, AnswerStatus, BankQuestion, Game, Lifeline, OptionIndex, PracticeStats, Question,
+    },
+    ports::{GameDatabase, QuestionStore, UserModel, UsersDatabase},
 };
 use async_trait::async_trait;
 use std::{collections::HashMap, sync::Arc};
@@ -76,9 +78,47 @@ impl UsersDatabase for UsersMemoryDatabase {
 }
 #[derive(Debug, Clone, Default)]
 pub struct GameMemoryDatabase {
-    answers: Arc<Mutex<HashMap<(String, String), OptionIndex>>>,
+    game: Arc<Mutex<Option<Game>>>,
+    answers: Arc<Mutex<HashMap<(String, String), Answer>>>,
     answer_statuses: Arc<Mutex<HashMap<(String, String), AnswerStatus>>>,
     scores: Arc<Mutex<HashMap<String, u32>>>,
+    lifelines: Arc<Mutex<Vec<(String, String, Lifeline)>>>,
+    packs: Arc<Mutex<HashMap<String, Game>>>,
+    ratings: Arc<Mutex<HashMap<String, u32>>>,
+    practice_stats: Arc<Mutex<HashMap<String, PracticeStats>>>,
+}
+
+/// Name of the built-in question pack.
+pub const DEFAULT_PACK: &str = "default";
+
+fn sample_game() -> Game {
+    Game {
+        questions: vec![
+            Question::choice(
+                "What is question 1?".into(),
+                vec![
+                    "Option 1".into(),
+                    "Option 2".into(),
+                    "Option 3".into(),
+                    "Option 4".into(),
+                ],
+                OptionIndex::One,
+            ),
+            Question::choice(
+                "What is question 2?".into(),
+                vec![
+                    "Option 1".into(),
+                    "Option 2".into(),
+                    "Option 3".into(),
+                    "Option 4".into(),
+                ],
+                OptionIndex::One,
+            ),
+            Question::true_false("Is this question 3?".into(), true),
+            Question::text("What is question 4?".into(), vec!["Answer 4".into()]),
+            Question::number("What is question 5?".into(), 5.0, 5.0),
+        ],
+    }
 }
 
 #[async_trait]
@@ -86,52 +126,60 @@ impl GameDatabase for GameMemoryDatabase {
     type Error = std::convert::Infallible;
 
     async fn get_game(&self) -> Result<Option<Game>, Self::Error> {
-        Ok(Some(Game {
-            questions: vec![
-                Question {
-                    question: "What is question 1?".into(),
-                    options: [
-                        "Option 1".into(),
-                        "Option 2".into(),
-                        "Option 3".into(),
-                        "Option 4".into(),
-                    ],
-                    answer_idx: OptionIndex::One,
-                },
-                Question {
-                    question: "What is question 2?".into(),
-                    options: [
-                        "Option 1".into(),
-                        "Option 2".into(),
-                        "Option 3".into(),
-                        "Option 4".into(),
-                    ],
-                    answer_idx: OptionIndex::One,
-                },
-            ],
-        }))
+        let game = self.game.lock().await;
+        Ok(Some(game.clone().unwrap_or_else(sample_game)))
+    }
+
+    async fn set_game(&self, game: &Game) -> Result<(), Self::Error> {
+        *self.game.lock().await = Some(game.clone());
+        Ok(())
+    }
+
+    async fn clear_game(&self) -> Result<(), Self::Error> {
+        *self.game.lock().await = None;
+        Ok(())
+    }
+
+    async fn get_pack(&self, name: &str) -> Result<Option<Game>, Self::Error> {
+        if name == DEFAULT_PACK {
+            return Ok(Some(sample_game()));
+        }
+
+        let packs = self.packs.lock().await;
+        Ok(packs.get(name).cloned())
+    }
+
+    async fn set_pack(&self, name: &str, game: &Game) -> Result<(), Self::Error> {
+        let mut packs = self.packs.lock().await;
+        packs.insert(name.into(), game.clone());
+        Ok(())
     }
 
     async fn set_answer(
         &self,
         id: &str,
         question: &str,
-        answer: OptionIndex,
+        answer: Answer,
     ) -> Result<(), Self::Error> {
         let mut answers = self.answers.lock().await;
         answers.insert((id.into(), question.into()), answer);
         Ok(())
     }
 
-    async fn get_answer(
-        &self,
-        id: &str,
-        question: &str,
-    ) -> Result<Option<OptionIndex>, Self::Error> {
+    async fn get_answer(&self, id: &str, question: &str) -> Result<Option<Answer>, Self::Error> {
         let answers = self.answers.lock().await;
         Ok(answers.get(&(id.into(), question.into())).cloned())
     }
 
+    async fn get_answers(&self, question: &str) -> Result<Vec<Answer>, Self::Error> {
+        let answers = self.answers.lock().await;
+        Ok(answers
+            .iter()
+            .filter(|((_, q), _)| q == question)
+            .map(|(_, answer)| answer.clone())
+            .collect())
+    }
+
     async fn set_answer_status(
         &self,
         id: &str,
@@ -143,6 +191,15 @@ impl GameDatabase for GameMemoryDatabase {
         Ok(())
     }
 
+    async fn get_answer_status(
+        &self,
+        id: &str,
+        question: &str,
+    ) -> Result<Option<AnswerStatus>, Self::Error> {
+        let answer_statuses = self.answer_statuses.lock().await;
+        Ok(answer_statuses.get(&(id.into(), question.into())).cloned())
+    }
+
     async fn get_answers_statuses(&self, username: &str) -> Result<Vec<AnswerStatus>, Self::Error> {
         let answer_statuses = self.answer_statuses.lock().await;
         Ok(answer_statuses
@@ -157,4 +214,90 @@ impl GameDatabase for GameMemoryDatabase {
         scores.insert(username.into(), score);
         Ok(())
     }
+
+    async fn add_lifeline(
+        &self,
+        id: &str,
+        question: &str,
+        lifeline: Lifeline,
+    ) -> Result<(), Self::Error> {
+        let mut lifelines = self.lifelines.lock().await;
+        lifelines.push((id.into(), question.into(), lifeline));
+        Ok(())
+    }
+
+    async fn get_lifelines(&self, id: &str) -> Result<Vec<(String, Lifeline)>, Self::Error> {
+        let lifelines = self.lifelines.lock().await;
+        Ok(lifelines
+            .iter()
+            .filter(|(user, _, _)| user == id)
+            .map(|(_, question, lifeline)| (question.clone(), *lifeline))
+            .collect())
+    }
+
+    async fn clear_lifelines(&self, id: &str) -> Result<(), Self::Error> {
+        let mut lifelines = self.lifelines.lock().await;
+        lifelines.retain(|(user, _, _)| user != id);
+        Ok(())
+    }
+
+    async fn clear_prefixed(&self, prefix: &str) -> Result<(), Self::Error> {
+        let kept = |id: &String| !id.starts_with(prefix);
+        self.answers.lock().await.retain(|(id, _), _| kept(id));
+        self.answer_statuses
+            .lock()
+            .await
+            .retain(|(id, _), _| kept(id));
+        self.scores.lock().await.retain(|id, _| kept(id));
+        self.lifelines.lock().await.retain(|(id, _, _)| kept(id));
+        Ok(())
+    }
+
+    async fn get_rating(&self, id: &str) -> Result<Option<u32>, Self::Error> {
+        let ratings = self.ratings.lock().await;
+        Ok(ratings.get(id).copied())
+    }
+
+    async fn set_rating(&self, id: &str, rating: u32) -> Result<(), Self::Error> {
+        let mut ratings = self.ratings.lock().await;
+        ratings.insert(id.into(), rating);
+        Ok(())
+    }
+
+    async fn get_practice_stats(&self, id: &str) -> Result<Option<PracticeStats>, Self::Error> {
+        let practice_stats = self.practice_stats.lock().await;
+        Ok(practice_stats.get(id).copied())
+    }
+
+    async fn set_practice_stats(&self, id: &str, stats: &PracticeStats) -> Result<(), Self::Error> {
+        let mut practice_stats = self.practice_stats.lock().await;
+        practice_stats.insert(id.into(), *stats);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QuestionMemoryStore {
+    questions: Arc<Mutex<HashMap<String, BankQuestion>>>,
+}
+
+#[async_trait]
+impl QuestionStore for QuestionMemoryStore {
+    type Error = std::convert::Infallible;
+
+    async fn get_question(&self, id: &str) -> Result<Option<BankQuestion>, Self::Error> {
+        let questions = self.questions.lock().await;
+        Ok(questions.get(id).cloned())
+    }
+
+    async fn get_questions(&self) -> Result<Vec<BankQuestion>, Self::Error> {
+        let questions = self.questions.lock().await;
+        Ok(questions.values().cloned().collect())
+    }
+
+    async fn set_question(&self, question: &BankQuestion) -> Result<(), Self::Error> {
+        let mut questions = self.questions.lock().await;
+        questions.insert(question.id.clone(), question.clone());
+        Ok(())
+    }
 }
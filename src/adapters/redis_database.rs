@@ -1,6 +1,6 @@
 use crate::{
-    models::{AnswerStatus, Game},
-    ports::{GameDatabase, UserModel, UsersDatabase},
+    models::{Answer, AnswerStatus, BankQuestion, Game, Lifeline, PracticeStats},
+    ports::{GameDatabase, QuestionStore, UserModel, UsersDatabase},
 };
 use async_trait::async_trait;
 use redis::{aio::Connection, cmd, AsyncCommands, Client, JsonAsyncCommands, RedisError, Value};
@@ -101,15 +101,12 @@ fn string_from_redis_value(v: &Value) -> Option<String> {
     }
 }
 
-#[async_trait]
-impl GameDatabase for RedisUsersDatabase {
-    type Error = RedisError;
-
-    async fn get_game(&self) -> Result<Option<Game>, Self::Error> {
+impl RedisUsersDatabase {
+    async fn get_json_game(&self, key: &str) -> Result<Option<Game>, RedisError> {
         let connection = self.connection.clone();
         let mut connection = connection.lock().await;
 
-        let data: Value = connection.json_get("game:latest", ".").await?;
+        let data: Value = connection.json_get(key, ".").await?;
 
         match data {
             Value::Data(data) => {
@@ -119,33 +116,100 @@ impl GameDatabase for RedisUsersDatabase {
             _ => Ok(None),
         }
     }
+}
+
+fn escape_pattern(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| match c {
+            '*' | '?' | '[' | ']' | '\\' => vec!['\\', c],
+            c => vec![c],
+        })
+        .collect()
+}
+
+#[async_trait]
+impl GameDatabase for RedisUsersDatabase {
+    type Error = RedisError;
+
+    async fn get_game(&self) -> Result<Option<Game>, Self::Error> {
+        self.get_json_game("game:latest").await
+    }
+
+    async fn set_game(&self, game: &Game) -> Result<(), Self::Error> {
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        connection
+            .json_set::<_, _, _, ()>("game:latest", ".", game)
+            .await?;
+        Ok(())
+    }
+
+    async fn clear_game(&self) -> Result<(), Self::Error> {
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        connection.del::<_, ()>("game:latest").await?;
+        Ok(())
+    }
+
+    async fn get_pack(&self, name: &str) -> Result<Option<Game>, Self::Error> {
+        self.get_json_game(&format!("pack:{name}")).await
+    }
+
+    async fn set_pack(&self, name: &str, game: &Game) -> Result<(), Self::Error> {
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        connection
+            .json_set::<_, _, _, ()>(format!("pack:{name}"), ".", game)
+            .await?;
+        Ok(())
+    }
 
     async fn set_answer(
         &self,
         id: &str,
         question: &str,
-        answer: crate::models::OptionIndex,
+        answer: Answer,
     ) -> Result<(), Self::Error> {
         let connection = self.connection.clone();
         let mut connection = connection.lock().await;
         let answer = serde_json::to_string(&answer)?;
         connection
-            .set(format!("answer:{id}:{question}"), answer)
+            .set::<_, _, ()>(format!("answer:{id}:{question}"), answer)
+            .await?;
+        // ids and questions may contain `:`, so the players who answered a
+        // question are listed rather than matched by key pattern
+        connection
+            .sadd::<_, _, ()>(format!("answered:{question}"), id)
             .await?;
         Ok(())
     }
 
-    async fn get_answer(
-        &self,
-        id: &str,
-        question: &str,
-    ) -> Result<Option<crate::models::OptionIndex>, Self::Error> {
+    async fn get_answer(&self, id: &str, question: &str) -> Result<Option<Answer>, Self::Error> {
         let connection = self.connection.clone();
         let mut connection = connection.lock().await;
         let answer: Option<String> = connection.get(format!("answer:{id}:{question}")).await?;
         Ok(answer.and_then(|answer| serde_json::from_str(&answer).ok()))
     }
 
+    async fn get_answers(&self, question: &str) -> Result<Vec<Answer>, Self::Error> {
+        let c = self.connection.clone();
+        let mut c = c.lock().await;
+        let ids: Vec<String> = c.smembers(format!("answered:{question}")).await?;
+
+        let mut res = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            let val: Option<String> = c.get(format!("answer:{id}:{question}")).await?;
+            let val: Option<Answer> = val.and_then(|val| serde_json::from_str(&val).ok());
+
+            if let Some(val) = val {
+                res.push(val)
+            }
+        }
+
+        Ok(res)
+    }
+
     async fn set_answer_status(
         &self,
         id: &str,
@@ -156,11 +220,24 @@ impl GameDatabase for RedisUsersDatabase {
         let mut connection = connection.lock().await;
         let answer_status = serde_json::to_string(&answer_status)?;
         connection
-            .set(format!("answer_status:{id}:{question}"), answer_status)
+            .set::<_, _, ()>(format!("answer_status:{id}:{question}"), answer_status)
             .await?;
         Ok(())
     }
 
+    async fn get_answer_status(
+        &self,
+        id: &str,
+        question: &str,
+    ) -> Result<Option<AnswerStatus>, Self::Error> {
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        let answer_status: Option<String> = connection
+            .get(format!("answer_status:{id}:{question}"))
+            .await?;
+        Ok(answer_status.and_then(|answer_status| serde_json::from_str(&answer_status).ok()))
+    }
+
     async fn get_answers_statuses(&self, id: &str) -> Result<Vec<AnswerStatus>, Self::Error> {
         let c = self.connection.clone();
         let mut c = c.lock().await;
@@ -187,7 +264,143 @@ impl GameDatabase for RedisUsersDatabase {
         let connection = self.connection.clone();
         let mut connection = connection.lock().await;
         connection
-            .set(format!("score:{id}"), score.to_string())
+            .set::<_, _, ()>(format!("score:{id}"), score.to_string())
+            .await?;
+        Ok(())
+    }
+
+    async fn add_lifeline(
+        &self,
+        id: &str,
+        question: &str,
+        lifeline: Lifeline,
+    ) -> Result<(), Self::Error> {
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        let lifeline = serde_json::to_string(&(question, lifeline))?;
+        connection
+            .rpush::<_, _, ()>(format!("lifelines:{id}"), lifeline)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_lifelines(&self, id: &str) -> Result<Vec<(String, Lifeline)>, Self::Error> {
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        let lifelines: Vec<String> = connection.lrange(format!("lifelines:{id}"), 0, -1).await?;
+
+        Ok(lifelines
+            .iter()
+            .filter_map(|lifeline| serde_json::from_str(lifeline).ok())
+            .collect())
+    }
+
+    async fn clear_lifelines(&self, id: &str) -> Result<(), Self::Error> {
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        connection.del::<_, ()>(format!("lifelines:{id}")).await?;
+        Ok(())
+    }
+
+    async fn clear_prefixed(&self, prefix: &str) -> Result<(), Self::Error> {
+        let c = self.connection.clone();
+        let mut c = c.lock().await;
+        let prefix = escape_pattern(prefix);
+
+        for kind in ["answer", "answered", "answer_status", "score", "lifelines"] {
+            let mut keys: Vec<String> = Vec::new();
+            let mut iter = c.scan_match(format!("{kind}:{prefix}*")).await?;
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+            drop(iter);
+
+            if !keys.is_empty() {
+                c.del::<_, ()>(keys).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_rating(&self, id: &str) -> Result<Option<u32>, Self::Error> {
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        let rating: Option<u32> = connection.get(format!("rating:{id}")).await?;
+        Ok(rating)
+    }
+
+    async fn set_rating(&self, id: &str, rating: u32) -> Result<(), Self::Error> {
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        connection
+            .set::<_, _, ()>(format!("rating:{id}"), rating)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_practice_stats(&self, id: &str) -> Result<Option<PracticeStats>, Self::Error> {
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        let stats: Option<String> = connection.get(format!("practice_stats:{id}")).await?;
+        Ok(stats.and_then(|stats| serde_json::from_str(&stats).ok()))
+    }
+
+    async fn set_practice_stats(&self, id: &str, stats: &PracticeStats) -> Result<(), Self::Error> {
+        let stats = serde_json::to_string(stats)?;
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        connection
+            .set::<_, _, ()>(format!("practice_stats:{id}"), stats)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Each question is a JSON string under `bank:question:{id}`, and the set
+/// `bank:questions` holds their ids.
+#[async_trait]
+impl QuestionStore for RedisUsersDatabase {
+    type Error = RedisError;
+
+    async fn get_question(&self, id: &str) -> Result<Option<BankQuestion>, Self::Error> {
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        let question: Option<String> = connection.get(format!("bank:question:{id}")).await?;
+        Ok(question
+            .map(|question| serde_json::from_str(&question))
+            .transpose()?)
+    }
+
+    async fn get_questions(&self) -> Result<Vec<BankQuestion>, Self::Error> {
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        let ids: Vec<String> = connection.smembers("bank:questions").await?;
+
+        let mut questions = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            let question: Option<String> = connection.get(format!("bank:question:{id}")).await?;
+            // One unreadable question should not hide the rest of the bank.
+            match question.map(|question| serde_json::from_str(&question)) {
+                Some(Ok(question)) => questions.push(question),
+                Some(Err(e)) => log::error!("Failed to parse bank question {id}: {e}"),
+                None => (),
+            }
+        }
+
+        Ok(questions)
+    }
+
+    async fn set_question(&self, question: &BankQuestion) -> Result<(), Self::Error> {
+        let json = serde_json::to_string(question)?;
+        let connection = self.connection.clone();
+        let mut connection = connection.lock().await;
+        connection
+            .set::<_, _, ()>(format!("bank:question:{}", question.id), json)
+            .await?;
+        connection
+            .sadd::<_, _, ()>("bank:questions", &question.id)
             .await?;
         Ok(())
     }
@@ -1,6 +1,6 @@
-use crate::ports::{GameStartNotifier, JobSchedular};
+use crate::ports::{GamePreparer, GameStartNotifier, JobSchedular};
 use async_trait::async_trait;
-use std::time::Duration;
+use std::{convert::Infallible, time::Duration};
 use thiserror::Error;
 use tokio_cron_scheduler::Job;
 
@@ -20,15 +20,44 @@ pub enum SchedularError {
     CouldNotGetSystemTime(#[from] std::time::SystemTimeError),
 }
 
+/// Leaves the next game to whatever is in the database.
+#[derive(Clone)]
+struct Unprepared;
+
+#[async_trait]
+impl GamePreparer for Unprepared {
+    type Error = Infallible;
+
+    async fn prepare_game(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
 impl Schedular {
     pub async fn new<N: GameStartNotifier + Clone + Send + Sync + 'static>(
         notifier: N,
     ) -> Result<Self, tokio_cron_scheduler::JobSchedulerError> {
+        Self::with_preparer(notifier, Unprepared).await
+    }
+
+    /// Has `preparer` get each game ready before its start signal is sent.
+    pub async fn with_preparer<N, P>(
+        notifier: N,
+        preparer: P,
+    ) -> Result<Self, tokio_cron_scheduler::JobSchedulerError>
+    where
+        N: GameStartNotifier + Clone + Send + Sync + 'static,
+        P: GamePreparer + Clone + Send + Sync + 'static,
+    {
         let schedular = tokio_cron_scheduler::JobScheduler::new().await?;
 
         let game_start_job = Job::new_async("1/100 * * * * *", move |_, _| {
             let notifier = notifier.clone();
-            Box::pin(notifier_callback(notifier))
+            let preparer = preparer.clone();
+            Box::pin(async move {
+                preparer_callback(preparer).await;
+                notifier_callback(notifier).await;
+            })
         })?;
 
         let job_id = game_start_job.guid();
@@ -39,6 +68,15 @@ impl Schedular {
     }
 }
 
+async fn preparer_callback<P: GamePreparer>(preparer: P) {
+    if let Err(e) = preparer.prepare_game().await {
+        log::warn!(
+            "Failed to prepare the next game, the last one will not be replayed: {}",
+            e
+        );
+    }
+}
+
 async fn notifier_callback<N: GameStartNotifier + Clone + Send + Sync + 'static>(notifier: N) {
     match notifier.send_signal().await {
         Ok(()) => {
@@ -54,7 +92,7 @@ async fn notifier_callback<N: GameStartNotifier + Clone + Send + Sync + 'static>
 impl JobSchedular for Schedular {
     type Error = SchedularError;
 
-    async fn time_till_game(&mut self) -> Result<Duration, Self::Error> {
+    async fn time_till_game(&mut self) -> Result<Option<Duration>, Self::Error> {
         let t = self
             .schedular
             .next_tick_for_job(self.job_id)
@@ -63,6 +101,6 @@ impl JobSchedular for Schedular {
             .timestamp();
         let t = Duration::from_secs(t as u64);
         let n = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
-        Ok(t - n)
+        Ok(Some(t - n))
     }
 }
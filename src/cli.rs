@@ -0,0 +1,235 @@
+use hyper::{body::Bytes, client::HttpConnector, Body, Client, Method, Request, StatusCode};
+use segon::{
+    controllers::ImportReport,
+    models::PackFormat,
+    request::{QuestionPackRequest, QuestionSearchRequest},
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const USAGE: &str = "usage:
+    segon questions import <file> [--format json|csv|yaml] [--dry-run]
+    segon questions export <file> [--format json|csv|yaml] [--category <category>] [--include-retired]
+
+packs go to the server at $SEGON_URL, sent by the admin whose token is $SEGON_TOKEN";
+
+const SERVER_VAR: &str = "SEGON_URL";
+const DEFAULT_SERVER: &str = "http://127.0.0.1:3030";
+const TOKEN_VAR: &str = "SEGON_TOKEN";
+
+struct Options<'a> {
+    file: &'a str,
+    format: Option<PackFormat>,
+    dry_run: bool,
+    category: Option<String>,
+    include_retired: bool,
+}
+
+fn parse_options(args: &[String]) -> Result<Options<'_>, String> {
+    let (file, mut rest) = match args {
+        [file, rest @ ..] if !file.starts_with("--") => (file.as_str(), rest.iter()),
+        _ => return Err("missing pack file".into()),
+    };
+
+    let mut options = Options {
+        file,
+        format: None,
+        dry_run: false,
+        category: None,
+        include_retired: false,
+    };
+
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--dry-run" => options.dry_run = true,
+            "--include-retired" => options.include_retired = true,
+            "--format" => {
+                let name = rest.next().ok_or("--format needs a value")?;
+                let format =
+                    PackFormat::from_name(name).ok_or(format!("unknown pack format {name}"))?;
+                options.format = Some(format);
+            }
+            "--category" => {
+                let category = rest.next().ok_or("--category needs a value")?;
+                options.category = Some(category.clone());
+            }
+            arg => return Err(format!("unknown option {arg}")),
+        }
+    }
+
+    Ok(options)
+}
+
+/// What the admin endpoints reply with, whether they succeed or not.
+#[derive(Deserialize)]
+struct Reply {
+    status: String,
+    message: Option<String>,
+    report: Option<ImportReport>,
+}
+
+impl Reply {
+    fn parse(status: StatusCode, body: &[u8]) -> Self {
+        serde_json::from_slice(body).unwrap_or_else(|_| Reply {
+            status: status.to_string(),
+            message: None,
+            report: None,
+        })
+    }
+
+    fn error(&self) -> String {
+        match &self.message {
+            Some(message) => format!("{}: {message}", self.status),
+            None => self.status.clone(),
+        }
+    }
+}
+
+/// The question bank of the running server, reached through its admin
+/// endpoints, so packs land in the store games are composed from.
+struct Server {
+    url: String,
+    token: String,
+    client: Client<HttpConnector>,
+}
+
+impl Server {
+    fn from_env() -> Result<Self, String> {
+        let url = std::env::var(SERVER_VAR).unwrap_or_else(|_| DEFAULT_SERVER.into());
+        let token = std::env::var(TOKEN_VAR).or(Err(format!("{TOKEN_VAR} is not set")))?;
+
+        Ok(Self {
+            url: url.trim_end_matches('/').into(),
+            token,
+            client: Client::new(),
+        })
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Body,
+    ) -> Result<(StatusCode, Bytes), String> {
+        let request = Request::builder()
+            .method(method)
+            .uri(format!("{}{path}", self.url))
+            .header("authorization", format!("Bearer {}", self.token))
+            .body(body)
+            .map_err(|err| err.to_string())?;
+
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|err| format!("failed to reach the server at {}: {err}", self.url))?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok((status, body))
+    }
+}
+
+fn query<T: Serialize>(value: &T) -> String {
+    serde_urlencoded::to_string(value).unwrap_or_default()
+}
+
+/// Runs `segon questions ...` against the question bank of the running
+/// server, and returns the exit code.
+pub async fn run(args: &[String]) -> i32 {
+    let (command, options) = match args {
+        [command, rest @ ..] if matches!(command.as_str(), "import" | "export") => {
+            (command.as_str(), parse_options(rest))
+        }
+        [command, ..] => ("", Err(format!("unknown command {command}"))),
+        [] => ("", Err("missing command".into())),
+    };
+
+    let options = match options {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            return 2;
+        }
+    };
+
+    let format = options
+        .format
+        .or_else(|| PackFormat::from_path(Path::new(options.file)))
+        .unwrap_or_default();
+    let pack = QuestionPackRequest {
+        format,
+        dry_run: options.dry_run,
+    };
+
+    let server = match Server::from_env() {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            return 2;
+        }
+    };
+
+    if command == "export" {
+        let search = QuestionSearchRequest {
+            category: options.category,
+            include_retired: options.include_retired,
+            ..Default::default()
+        };
+        let path = format!("/questions/export?{}&{}", query(&pack), query(&search));
+
+        let written = match server.send(Method::GET, &path, Body::empty()).await {
+            Ok((StatusCode::OK, pack)) => {
+                std::fs::write(options.file, pack).map_err(|err| err.to_string())
+            }
+            Ok((status, body)) => Err(Reply::parse(status, &body).error()),
+            Err(err) => Err(err),
+        };
+        return match written {
+            Ok(()) => 0,
+            Err(err) => {
+                eprintln!("{err}");
+                1
+            }
+        };
+    }
+
+    let source = match std::fs::read(options.file) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("failed to read {}: {err}", options.file);
+            return 1;
+        }
+    };
+
+    let path = format!("/questions/import?{}", query(&pack));
+    let reply = match server.send(Method::POST, &path, Body::from(source)).await {
+        Ok((status, body)) => Reply::parse(status, &body),
+        Err(err) => {
+            eprintln!("{err}");
+            return 1;
+        }
+    };
+
+    let Some(report) = &reply.report else {
+        eprintln!("{}", reply.error());
+        return 1;
+    };
+
+    for err in &report.errors {
+        eprintln!("{}: {err}", options.file);
+    }
+
+    if !report.errors.is_empty() {
+        eprintln!("{} rows to fix, nothing imported", report.errors.len());
+        1
+    } else if report.imported {
+        println!("imported {} questions", report.questions.len());
+        0
+    } else {
+        println!("{} questions can be imported", report.questions.len());
+        0
+    }
+}
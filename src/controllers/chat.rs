@@ -0,0 +1,226 @@
+use crate::{controllers::normalize, models::ServerMessage};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::time::Instant;
+
+pub const MAX_CHAT_LENGTH: usize = 200;
+pub const CHAT_RATE_LIMIT: usize = 3;
+pub const CHAT_RATE_WINDOW: Duration = Duration::from_secs(5);
+const CHAT_HISTORY: usize = 100;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ChatError {
+    #[error("chat is read-only while a question is open")]
+    ReadOnly,
+    #[error("chat message is empty")]
+    Empty,
+    #[error("chat message is longer than {MAX_CHAT_LENGTH} characters")]
+    TooLong,
+    #[error("you have been muted")]
+    Muted,
+    #[error("you are sending messages too quickly")]
+    RateLimited,
+    #[error("only moderators can do that")]
+    NotModerator,
+    #[error("requested chat message was not found")]
+    MessageNotFound,
+}
+
+/// Masks words from a configurable list, ignoring case and accents.
+#[derive(Debug, Clone, Default)]
+pub struct ChatFilter {
+    words: HashSet<String>,
+}
+
+impl ChatFilter {
+    pub fn new<I: IntoIterator<Item = String>>(words: I) -> Self {
+        Self {
+            words: words
+                .into_iter()
+                .map(|word| normalize(&word))
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Reads a word list with one word per line.
+    pub fn parse(list: &str) -> Self {
+        Self::new(list.lines().map(String::from))
+    }
+
+    pub fn censor(&self, text: &str) -> String {
+        text.split(' ')
+            .map(|word| {
+                let core = word.trim_matches(|c: char| !c.is_alphanumeric());
+                if !core.is_empty() && self.words.contains(&normalize(core)) {
+                    word.replace(core, &"*".repeat(core.chars().count()))
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Lobby chat of one game controller.
+#[derive(Debug, Default)]
+pub struct Chat {
+    filter: ChatFilter,
+    moderators: HashSet<String>,
+    muted: HashSet<String>,
+    sent: HashMap<String, VecDeque<Instant>>,
+    history: VecDeque<u64>,
+    next_id: u64,
+}
+
+impl Chat {
+    pub fn new(filter: ChatFilter) -> Self {
+        Self {
+            filter,
+            ..Self::default()
+        }
+    }
+
+    pub fn add_moderator(&mut self, user_id: &str) {
+        self.moderators.insert(user_id.into());
+    }
+
+    pub fn post(
+        &mut self,
+        user_id: &str,
+        text: &str,
+        now: Instant,
+    ) -> Result<ServerMessage, ChatError> {
+        use ChatError::*;
+        let text = text.trim();
+
+        if text.is_empty() {
+            return Err(Empty);
+        }
+
+        if text.chars().count() > MAX_CHAT_LENGTH {
+            return Err(TooLong);
+        }
+
+        if self.muted.contains(user_id) {
+            return Err(Muted);
+        }
+
+        let sent = self.sent.entry(user_id.into()).or_default();
+        while sent
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= CHAT_RATE_WINDOW)
+        {
+            sent.pop_front();
+        }
+
+        if sent.len() >= CHAT_RATE_LIMIT {
+            return Err(RateLimited);
+        }
+        sent.push_back(now);
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.history.push_back(id);
+        if self.history.len() > CHAT_HISTORY {
+            self.history.pop_front();
+        }
+
+        Ok(ServerMessage::Chat {
+            id,
+            user_id: user_id.into(),
+            text: self.filter.censor(text),
+        })
+    }
+
+    pub fn mute(&mut self, moderator: &str, user_id: &str) -> Result<ServerMessage, ChatError> {
+        if !self.moderators.contains(moderator) {
+            return Err(ChatError::NotModerator);
+        }
+
+        self.muted.insert(user_id.into());
+        Ok(ServerMessage::ChatMuted {
+            user_id: user_id.into(),
+        })
+    }
+
+    pub fn delete(&mut self, moderator: &str, id: u64) -> Result<ServerMessage, ChatError> {
+        if !self.moderators.contains(moderator) {
+            return Err(ChatError::NotModerator);
+        }
+
+        let idx = self
+            .history
+            .iter()
+            .position(|sent| *sent == id)
+            .ok_or(ChatError::MessageNotFound)?;
+        self.history.remove(idx);
+        Ok(ServerMessage::ChatDeleted { id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chat, ChatError, ChatFilter, CHAT_RATE_LIMIT, CHAT_RATE_WINDOW};
+    use crate::models::ServerMessage;
+    use tokio::time::Instant;
+
+    #[test]
+    fn censors_listed_words() {
+        let filter = ChatFilter::parse("darn\nHeck\n");
+
+        assert_eq!(
+            filter.censor("Darn it, what the hëck!"),
+            "**** it, what the ****!"
+        );
+        assert_eq!(filter.censor("darning is fine"), "darning is fine");
+    }
+
+    #[test]
+    fn limits_how_often_players_post() {
+        let mut chat = Chat::default();
+        let now = Instant::now();
+
+        for _ in 0..CHAT_RATE_LIMIT {
+            assert!(chat.post("user", "hi", now).is_ok());
+        }
+        assert!(matches!(
+            chat.post("user", "hi", now),
+            Err(ChatError::RateLimited)
+        ));
+        assert!(chat.post("other", "hi", now).is_ok());
+        assert!(chat.post("user", "hi", now + CHAT_RATE_WINDOW).is_ok());
+    }
+
+    #[test]
+    fn only_moderators_mute_and_delete() {
+        let mut chat = Chat::default();
+        chat.add_moderator("admin");
+        let now = Instant::now();
+
+        let id = match chat.post("user", "hello", now) {
+            Ok(ServerMessage::Chat { id, .. }) => id,
+            other => panic!("unexpected {other:?}"),
+        };
+
+        assert!(matches!(
+            chat.delete("user", id),
+            Err(ChatError::NotModerator)
+        ));
+        assert!(chat.delete("admin", id).is_ok());
+        assert!(matches!(
+            chat.delete("admin", id),
+            Err(ChatError::MessageNotFound)
+        ));
+
+        assert!(chat.mute("admin", "user").is_ok());
+        assert!(matches!(
+            chat.post("user", "hello", now),
+            Err(ChatError::Muted)
+        ));
+    }
+}
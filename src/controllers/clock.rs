@@ -0,0 +1,41 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+
+/// Milliseconds since the Unix epoch, the unit of every timestamp clients
+/// get from the server.
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+pub fn now_millis() -> u64 {
+    unix_millis(SystemTime::now())
+}
+
+/// Wall clock time of a game timer, which are kept as `Instant`s.
+pub fn deadline_millis(deadline: Instant) -> u64 {
+    let now = Instant::now();
+    let wall = now_millis();
+
+    if deadline >= now {
+        wall + (deadline - now).as_millis() as u64
+    } else {
+        wall.saturating_sub((now - deadline).as_millis() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deadline_millis, now_millis};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[test]
+    fn converts_timers_to_wall_clock() {
+        let deadline = deadline_millis(Instant::now() + Duration::from_secs(10));
+        let expected = now_millis() + 10_000;
+
+        assert!(deadline.abs_diff(expected) < 100);
+    }
+}
@@ -0,0 +1,492 @@
+use crate::{
+    controllers::{now_millis, LiveGame},
+    models::{BankQuestion, Difficulty, Game},
+    ports::{GameDatabase, GamePreparer, QuestionStore},
+};
+use async_trait::async_trait;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    Mutex,
+};
+
+pub const DEFAULT_GAME_QUESTIONS: usize = 10;
+pub const DEFAULT_COOLDOWN_DAYS: u64 = 7;
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// What a composed game has to look like.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Composition {
+    questions: usize,
+    categories: Vec<(String, u32)>,
+    difficulty_curve: Vec<Difficulty>,
+    cooldown_days: u64,
+}
+
+impl Default for Composition {
+    fn default() -> Self {
+        Self::new(DEFAULT_GAME_QUESTIONS)
+    }
+}
+
+impl Composition {
+    /// A game of `questions` questions from any category, getting harder
+    /// from start to end.
+    pub fn new(questions: usize) -> Self {
+        Self {
+            questions,
+            categories: Vec::new(),
+            difficulty_curve: vec![Difficulty::Easy, Difficulty::Medium, Difficulty::Hard],
+            cooldown_days: DEFAULT_COOLDOWN_DAYS,
+        }
+    }
+
+    /// Splits the game between categories by weight, so `[("history", 2),
+    /// ("science", 1)]` gives two history questions for every science one.
+    pub fn with_categories(mut self, categories: Vec<(String, u32)>) -> Self {
+        self.categories.clear();
+        for (category, weight) in categories {
+            let category = category.trim().to_lowercase();
+            match self.categories.iter_mut().find(|(c, _)| *c == category) {
+                Some((_, total)) => *total += weight,
+                None => self.categories.push((category, weight)),
+            }
+        }
+        self.categories.retain(|(_, weight)| *weight > 0);
+        self
+    }
+
+    /// Difficulties of successive parts of the game, each part getting an
+    /// equal share of the questions. An empty curve allows any difficulty.
+    pub fn with_difficulty_curve(mut self, curve: Vec<Difficulty>) -> Self {
+        self.difficulty_curve = curve;
+        self
+    }
+
+    /// Leaves out questions asked in the last `days` days.
+    pub fn with_cooldown_days(mut self, days: u64) -> Self {
+        self.cooldown_days = days;
+        self
+    }
+
+    /// How many questions each category gets, the remainders going to the
+    /// categories that lost the most to rounding.
+    fn quotas(&self) -> Vec<(Option<&str>, usize)> {
+        if self.categories.is_empty() {
+            return vec![(None, self.questions)];
+        }
+
+        let total: u64 = self.categories.iter().map(|(_, w)| *w as u64).sum();
+        let share = |weight: u32| self.questions as u64 * weight as u64;
+        let mut quotas: Vec<(Option<&str>, usize)> = self
+            .categories
+            .iter()
+            .map(|(category, weight)| (Some(category.as_str()), (share(*weight) / total) as usize))
+            .collect();
+
+        let mut by_remainder: Vec<usize> = (0..quotas.len()).collect();
+        by_remainder.sort_by_key(|idx| std::cmp::Reverse(share(self.categories[*idx].1) % total));
+        let assigned: usize = quotas.iter().map(|(_, quota)| quota).sum();
+        for idx in by_remainder.into_iter().take(self.questions - assigned) {
+            quotas[idx].1 += 1;
+        }
+
+        quotas
+    }
+
+    /// The difficulty wanted at each position of the game.
+    fn targets(&self) -> Vec<Option<Difficulty>> {
+        (0..self.questions)
+            .map(|idx| {
+                let part = idx * self.difficulty_curve.len() / self.questions;
+                self.difficulty_curve.get(part).copied()
+            })
+            .collect()
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ComposeError {
+    #[error("failed to get questions from the database")]
+    DatabaseError,
+    #[error("failed to store the composed game")]
+    GameNotStored,
+    #[error("{wanted} questions wanted but only {available} can be asked")]
+    NotEnoughQuestions { wanted: usize, available: usize },
+    #[error("{wanted} {category} questions wanted but only {available} can be asked")]
+    NotEnoughInCategory {
+        category: String,
+        wanted: usize,
+        available: usize,
+    },
+}
+
+fn distance(a: Difficulty, b: Difficulty) -> u32 {
+    (a as i32).abs_diff(b as i32)
+}
+
+/// Picks the questions of a game from the bank. Every category gets its
+/// quota, and each position gets the question closest to the curve among
+/// the categories that still have room.
+pub fn compose<R: Rng>(
+    bank: &[BankQuestion],
+    composition: &Composition,
+    now: u64,
+    rng: &mut R,
+) -> Result<Vec<BankQuestion>, ComposeError> {
+    let cooldown = composition.cooldown_days * DAY_MILLIS;
+    let mut eligible: Vec<&BankQuestion> = bank
+        .iter()
+        .filter(|question| !question.retired)
+        .filter(|question| {
+            question
+                .last_asked
+                .is_none_or(|asked| now.saturating_sub(asked) >= cooldown)
+        })
+        .collect();
+    // the bank comes in any order, which must not change a seeded game
+    eligible.sort_by(|a, b| a.id.cmp(&b.id));
+
+    if eligible.len() < composition.questions {
+        return Err(ComposeError::NotEnoughQuestions {
+            wanted: composition.questions,
+            available: eligible.len(),
+        });
+    }
+
+    let mut pools: Vec<(Vec<&BankQuestion>, usize)> = Vec::new();
+    for (category, quota) in composition.quotas() {
+        let pool: Vec<&BankQuestion> = eligible
+            .iter()
+            .copied()
+            .filter(|question| category.is_none_or(|category| question.category == category))
+            .collect();
+
+        if let Some(category) = category.filter(|_| pool.len() < quota) {
+            return Err(ComposeError::NotEnoughInCategory {
+                category: category.into(),
+                wanted: quota,
+                available: pool.len(),
+            });
+        }
+        pools.push((pool, quota));
+    }
+
+    let mut picked = Vec::with_capacity(composition.questions);
+    for target in composition.targets() {
+        let open: Vec<usize> = (0..pools.len()).filter(|idx| pools[*idx].1 > 0).collect();
+        let Ok(&pool_idx) = open.choose_weighted(rng, |idx| pools[*idx].1) else {
+            break;
+        };
+        let (pool, quota) = &mut pools[pool_idx];
+
+        let closest = |question: &&BankQuestion| {
+            target.map_or(0, |target| distance(question.difficulty, target))
+        };
+        let best = pool.iter().map(closest).min().unwrap_or_default();
+        let candidates: Vec<usize> = (0..pool.len())
+            .filter(|idx| closest(&pool[*idx]) == best)
+            .collect();
+        let Some(&idx) = candidates.choose(rng) else {
+            break;
+        };
+
+        picked.push(pool.swap_remove(idx).clone());
+        *quota -= 1;
+    }
+
+    Ok(picked)
+}
+
+/// Builds each scheduled game from the question bank and makes it the next
+/// game to be played.
+#[derive(Clone)]
+pub struct GameComposer<QS, GD>
+where
+    QS: QuestionStore,
+    GD: GameDatabase,
+{
+    store: QS,
+    db: GD,
+    composition: Composition,
+    rng: Arc<Mutex<StdRng>>,
+}
+
+impl<QS, GD> GameComposer<QS, GD>
+where
+    QS: QuestionStore,
+    GD: GameDatabase,
+{
+    pub fn new(store: QS, db: GD, composition: Composition) -> Self {
+        Self {
+            store,
+            db,
+            composition,
+            rng: Arc::new(Mutex::new(StdRng::from_entropy())),
+        }
+    }
+
+    /// Makes the composed games the same on every run.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Arc::new(Mutex::new(StdRng::seed_from_u64(seed)));
+        self
+    }
+
+    /// Composes a game. Its questions count as asked only once it starts.
+    pub async fn compose(&self) -> Result<Game, ComposeError> {
+        let bank = self
+            .store
+            .get_questions()
+            .await
+            .or(Err(ComposeError::DatabaseError))?;
+
+        let picked = {
+            let mut rng = self.rng.lock().await;
+            compose(&bank, &self.composition, now_millis(), &mut *rng)?
+        };
+
+        Ok(Game {
+            questions: picked
+                .into_iter()
+                .map(|question| question.question)
+                .collect(),
+        })
+    }
+}
+
+impl<QS, GD> GameComposer<QS, GD>
+where
+    QS: QuestionStore + Send + Sync,
+    GD: GameDatabase + Send + Sync,
+{
+    /// Marks the bank questions of the game as asked now.
+    pub async fn mark_asked(&self, game: &Game) -> Result<(), ComposeError> {
+        let bank = self
+            .store
+            .get_questions()
+            .await
+            .or(Err(ComposeError::DatabaseError))?;
+
+        let now = now_millis();
+        for question in bank
+            .into_iter()
+            .filter(|question| game.questions.contains(&question.question))
+        {
+            let asked = BankQuestion {
+                last_asked: Some(now),
+                ..question
+            };
+            self.store
+                .set_question(&asked)
+                .await
+                .or(Err(ComposeError::DatabaseError))?;
+        }
+        Ok(())
+    }
+
+    /// Marks the questions of each game as asked when it starts, so games
+    /// that are composed but never played do not use up the bank.
+    pub async fn record_asked(self, mut starts: broadcast::Receiver<LiveGame>) {
+        loop {
+            match starts.recv().await {
+                Ok(live) => {
+                    if let Err(e) = self.mark_asked(&live.game).await {
+                        log::error!("Failed to mark the questions of the game as asked: {e}");
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<QS, GD> GamePreparer for GameComposer<QS, GD>
+where
+    QS: QuestionStore + Send + Sync,
+    GD: GameDatabase + Send + Sync,
+{
+    type Error = ComposeError;
+
+    /// A game that cannot be composed leaves none behind, so the last one is
+    /// not played again.
+    async fn prepare_game(&self) -> Result<(), Self::Error> {
+        let game = match self.compose().await {
+            Ok(game) => game,
+            Err(e) => {
+                self.db
+                    .clear_game()
+                    .await
+                    .or(Err(ComposeError::GameNotStored))?;
+                return Err(e);
+            }
+        };
+        self.db
+            .set_game(&game)
+            .await
+            .or(Err(ComposeError::GameNotStored))?;
+
+        log::info!("Composed a game of {} questions", game.questions.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compose, ComposeError, Composition, GameComposer, DAY_MILLIS};
+    use crate::{
+        adapters::{GameMemoryDatabase, Notifier, QuestionMemoryStore, DEFAULT_PACK},
+        controllers::{GameController, ManualStart},
+        models::{BankQuestion, Difficulty, Question},
+        ports::{GameDatabase, GamePreparer, QuestionStore},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn bank() -> Vec<BankQuestion> {
+        let difficulties = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+        ["history", "science"]
+            .into_iter()
+            .flat_map(|category| {
+                (0..9).map(move |idx| BankQuestion {
+                    id: format!("{category}-{idx}"),
+                    question: Question::true_false(format!("{category} {idx}?"), true),
+                    category: category.into(),
+                    tags: Vec::new(),
+                    difficulty: difficulties[idx % 3],
+                    source: None,
+                    retired: false,
+                    last_asked: None,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn follows_the_category_mix_and_difficulty_curve() {
+        let composition =
+            Composition::new(6).with_categories(vec![("History".into(), 2), ("science".into(), 1)]);
+        let picked = compose(&bank(), &composition, 0, &mut StdRng::seed_from_u64(7)).unwrap();
+
+        let history = picked.iter().filter(|q| q.category == "history").count();
+        assert_eq!((picked.len(), history), (6, 4));
+
+        let difficulties: Vec<_> = picked.iter().map(|q| q.difficulty).collect();
+        assert_eq!(
+            difficulties,
+            vec![
+                Difficulty::Easy,
+                Difficulty::Easy,
+                Difficulty::Medium,
+                Difficulty::Medium,
+                Difficulty::Hard,
+                Difficulty::Hard
+            ]
+        );
+
+        let again = compose(&bank(), &composition, 0, &mut StdRng::seed_from_u64(7)).unwrap();
+        assert_eq!(picked, again);
+    }
+
+    #[test]
+    fn leaves_out_recently_asked_questions() {
+        let now = 30 * DAY_MILLIS;
+        let mut bank = bank();
+        for question in bank.iter_mut().skip(3) {
+            question.last_asked = Some(now - DAY_MILLIS);
+        }
+        bank[0].last_asked = Some(now - 8 * DAY_MILLIS);
+
+        let composition = Composition::new(3).with_cooldown_days(7);
+        let mut picked = compose(&bank, &composition, now, &mut StdRng::seed_from_u64(1))
+            .unwrap()
+            .into_iter()
+            .map(|q| q.id)
+            .collect::<Vec<_>>();
+        picked.sort();
+        assert_eq!(picked, vec!["history-0", "history-1", "history-2"]);
+
+        assert_eq!(
+            compose(
+                &bank,
+                &Composition::new(4),
+                now,
+                &mut StdRng::seed_from_u64(1)
+            ),
+            Err(ComposeError::NotEnoughQuestions {
+                wanted: 4,
+                available: 3
+            })
+        );
+        assert_eq!(
+            compose(
+                &bank,
+                &Composition::new(1).with_categories(vec![("science".into(), 1)]),
+                now,
+                &mut StdRng::seed_from_u64(1)
+            ),
+            Err(ComposeError::NotEnoughInCategory {
+                category: "science".into(),
+                wanted: 1,
+                available: 0
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn prepares_the_next_game() {
+        let store = QuestionMemoryStore::default();
+        for question in bank() {
+            store.set_question(&question).await.unwrap();
+        }
+        let db = GameMemoryDatabase::default();
+        let composer =
+            GameComposer::new(store.clone(), db.clone(), Composition::new(5)).with_seed(3);
+
+        composer.prepare_game().await.unwrap();
+        let game = db.get_game().await.unwrap().unwrap();
+        assert_eq!(game.questions.len(), 5);
+
+        let asked = || async {
+            store
+                .get_questions()
+                .await
+                .unwrap()
+                .into_iter()
+                .filter(|q| q.last_asked.is_some())
+                .count()
+        };
+        assert_eq!(asked().await, 0);
+
+        let controller = GameController::new(db, ManualStart, Notifier::new());
+        let recording = tokio::spawn(composer.record_asked(controller.game_starts()));
+        controller.begin_game().await.unwrap();
+        drop(controller);
+        recording.await.unwrap();
+        assert_eq!(asked().await, 5);
+    }
+
+    #[tokio::test]
+    async fn does_not_replay_the_last_game() {
+        let store = QuestionMemoryStore::default();
+        for question in bank() {
+            store.set_question(&question).await.unwrap();
+        }
+        let db = GameMemoryDatabase::default();
+        let composer = GameComposer::new(store, db.clone(), Composition::new(5));
+        composer.prepare_game().await.unwrap();
+
+        let composer = GameComposer::new(
+            QuestionMemoryStore::default(),
+            db.clone(),
+            Composition::new(5),
+        );
+        assert!(composer.prepare_game().await.is_err());
+        assert_eq!(
+            db.get_game().await.unwrap(),
+            db.get_pack(DEFAULT_PACK).await.unwrap()
+        );
+    }
+}
@@ -0,0 +1,511 @@
+use crate::{
+    controllers::{
+        encode_or_error, generate_join_code, greet_socket, GameController, ManualStart,
+        RoomDatabase, RoomGameController,
+    },
+    models::{DuelOutcome, ErrorCode, Game, ServerMessage},
+    ports::{GameDatabase, GameStartNotifier},
+};
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::{
+    sync::{oneshot, Mutex},
+    time::{sleep, timeout, Instant},
+};
+use warp::ws::Message;
+
+pub const DEFAULT_RATING: u32 = 1200;
+pub const DUEL_QUESTIONS: usize = 5;
+const RATING_K: f64 = 32.0;
+const MATCH_WINDOW: u32 = 100;
+const MATCH_WINDOW_GROWTH: u32 = 20;
+const MATCH_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+pub const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// How long a player who dropped out of a duel has to reconnect before they
+/// forfeit it.
+pub const RECONNECT_GRACE: Duration = Duration::from_secs(10);
+
+/// How far apart two ratings may be for the players to be paired. The window
+/// widens by `MATCH_WINDOW_GROWTH` for every second spent in the queue so
+/// that nobody waits forever.
+pub fn match_window(waited: Duration) -> u32 {
+    MATCH_WINDOW + MATCH_WINDOW_GROWTH * waited.as_secs() as u32
+}
+
+/// Elo rating of a player after a duel, where `result` is 1.0 for a win, 0.5
+/// for a draw and 0.0 for a loss.
+pub fn updated_rating(rating: u32, opponent_rating: u32, result: f64) -> u32 {
+    let expected = 1.0 / (1.0 + 10f64.powf((opponent_rating as f64 - rating as f64) / 400.0));
+    (rating as f64 + RATING_K * (result - expected))
+        .round()
+        .max(0.0) as u32
+}
+
+/// A duel as seen by one of its two players.
+#[derive(Clone)]
+pub struct DuelMatch<GD, GSN>
+where
+    GD: GameDatabase + Send + Sync,
+    GSN: GameStartNotifier,
+{
+    pub opponent: String,
+    pub rating: u32,
+    pub opponent_rating: u32,
+    pub controller: RoomGameController<GD, GSN>,
+}
+
+struct Waiting<GD, GSN>
+where
+    GD: GameDatabase + Send + Sync,
+    GSN: GameStartNotifier,
+{
+    user_id: String,
+    rating: u32,
+    since: Instant,
+    matched: oneshot::Sender<Result<DuelMatch<GD, GSN>, DuelError>>,
+}
+
+#[derive(Clone)]
+pub struct DuelsController<GD, GSN>
+where
+    GD: GameDatabase + Send + Sync,
+    GSN: GameStartNotifier,
+{
+    db: GD,
+    queue: Arc<Mutex<Vec<Waiting<GD, GSN>>>>,
+    /// Duels being played, keyed by each of their players.
+    duels: Arc<Mutex<HashMap<String, RoomGameController<GD, GSN>>>>,
+}
+
+impl<GD, GSN> DuelsController<GD, GSN>
+where
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+{
+    pub fn new(db: GD) -> Self {
+        Self {
+            db,
+            queue: Arc::new(Mutex::new(Vec::new())),
+            duels: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Number of players waiting for an opponent.
+    pub async fn queued(&self) -> usize {
+        self.queue.lock().await.len()
+    }
+
+    /// Queues the player and, once an opponent is found, hands the socket to
+    /// the duel's game controller. The handshake runs before queueing, so the
+    /// opponent is announced in the negotiated encoding. Leaving the queue is
+    /// done by closing the socket. A player who is still in a duel goes back
+    /// to it instead.
+    pub async fn start<Socket>(self, user_id: String, mut ws: Socket)
+    where
+        Socket:
+            Stream<Item = Result<Message, warp::Error>> + Sink<Message> + Send + Unpin + 'static,
+    {
+        let Some(greeted) = greet_socket(&mut ws).await else {
+            return;
+        };
+
+        let duel = self.duels.lock().await.get(&user_id).cloned();
+        if let Some(controller) = duel {
+            controller.start_greeted(user_id, ws, greeted).await;
+            return;
+        }
+
+        let found = tokio::select! {
+            found = self.find_match(&user_id) => found,
+            _ = closed(&mut ws) => {
+                self.leave_queue(&user_id).await;
+                return;
+            }
+        };
+
+        let msg = match found {
+            Ok(ref duel) => ServerMessage::DuelFound {
+                opponent: duel.opponent.clone(),
+                rating: duel.rating,
+                opponent_rating: duel.opponent_rating,
+            },
+            Err(ref e) => {
+                log::error!("Failed to find a duel for {user_id}: {e}");
+                ServerMessage::error(ErrorCode::Duel, e.to_string())
+            }
+        };
+
+        if ws
+            .send(encode_or_error(&msg, greeted.encoding()))
+            .await
+            .is_err()
+        {
+            log::error!("Failed to send duel to {user_id}");
+            return;
+        }
+
+        if let Ok(duel) = found {
+            duel.controller.start_greeted(user_id, ws, greeted).await;
+        }
+    }
+
+    pub async fn find_match(&self, user_id: &str) -> Result<DuelMatch<GD, GSN>, DuelError> {
+        let rating = self
+            .db
+            .get_rating(user_id)
+            .await
+            .or(Err(DuelError::DatabaseError))?
+            .unwrap_or(DEFAULT_RATING);
+
+        let (matched, mut rx) = oneshot::channel();
+        {
+            let mut queue = self.queue.lock().await;
+            queue.retain(|waiting| waiting.user_id != user_id);
+            queue.push(Waiting {
+                user_id: user_id.into(),
+                rating,
+                since: Instant::now(),
+                matched,
+            });
+        }
+
+        loop {
+            if let Some(opponent) = self.take_opponent(user_id).await {
+                // Spawned so that the opponent still gets their duel if this
+                // player leaves while it is being set up.
+                let this = self.clone();
+                let user_id = user_id.to_string();
+                return tokio::spawn(
+                    async move { this.create_duel(user_id, rating, opponent).await },
+                )
+                .await
+                .or(Err(DuelError::Cancelled))?;
+            }
+
+            match timeout(MATCH_RETRY_INTERVAL, &mut rx).await {
+                Ok(Ok(found)) => return found,
+                Ok(Err(_)) => return Err(DuelError::Cancelled),
+                Err(_) => continue,
+            }
+        }
+    }
+
+    async fn leave_queue(&self, user_id: &str) {
+        let mut queue = self.queue.lock().await;
+        queue.retain(|waiting| waiting.user_id != user_id);
+    }
+
+    /// Takes the player and the closest rated opponent within range out of
+    /// the queue. Returns `None` if there is no such opponent, or if another
+    /// player has already taken this one.
+    async fn take_opponent(&self, user_id: &str) -> Option<Waiting<GD, GSN>> {
+        let mut queue = self.queue.lock().await;
+        let own = queue
+            .iter()
+            .position(|waiting| waiting.user_id == user_id)?;
+        let rating = queue[own].rating;
+        let window = match_window(queue[own].since.elapsed());
+
+        let opponent = queue
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| *idx != own)
+            .map(|(idx, waiting)| (idx, waiting.rating.abs_diff(rating), waiting))
+            .filter(|(_, diff, waiting)| *diff <= window.max(match_window(waiting.since.elapsed())))
+            .min_by_key(|(_, diff, _)| *diff)
+            .map(|(idx, _, _)| idx)?;
+
+        let opponent = queue.remove(opponent);
+        queue.retain(|waiting| waiting.user_id != user_id);
+        Some(opponent)
+    }
+
+    async fn create_duel(
+        &self,
+        user_id: String,
+        rating: u32,
+        opponent: Waiting<GD, GSN>,
+    ) -> Result<DuelMatch<GD, GSN>, DuelError> {
+        let game = match self.duel_game().await {
+            Ok(game) => game,
+            Err(e) => {
+                let _ = opponent.matched.send(Err(e.clone()));
+                return Err(e);
+            }
+        };
+
+        let id = format!("duel-{}", generate_join_code(&mut rand::thread_rng()));
+        let notifier = GSN::default();
+        let db = RoomDatabase::new(self.db.clone(), id, game);
+        let controller = GameController::new(db.clone(), ManualStart, notifier.clone());
+
+        {
+            let mut duels = self.duels.lock().await;
+            duels.insert(user_id.clone(), controller.clone());
+            duels.insert(opponent.user_id.clone(), controller.clone());
+        }
+
+        tokio::spawn(self.clone().run_duel(
+            db,
+            controller.clone(),
+            notifier,
+            [
+                (user_id.clone(), rating),
+                (opponent.user_id.clone(), opponent.rating),
+            ],
+        ));
+
+        let _ = opponent.matched.send(Ok(DuelMatch {
+            opponent: user_id,
+            rating: opponent.rating,
+            opponent_rating: rating,
+            controller: controller.clone(),
+        }));
+
+        Ok(DuelMatch {
+            opponent: opponent.user_id,
+            rating,
+            opponent_rating: opponent.rating,
+            controller,
+        })
+    }
+
+    async fn duel_game(&self) -> Result<Game, DuelError> {
+        use DuelError::*;
+        let bank = self
+            .db
+            .get_game()
+            .await
+            .or(Err(DatabaseError))?
+            .ok_or(NoGame)?;
+
+        let game = bank.sample(&mut rand::thread_rng(), DUEL_QUESTIONS);
+        game.validate().or(Err(NoGame))?;
+        Ok(game)
+    }
+
+    /// Plays the duel, then deletes everything it stored once both players
+    /// have left.
+    async fn run_duel(
+        self,
+        db: RoomDatabase<GD>,
+        controller: RoomGameController<GD, GSN>,
+        notifier: GSN,
+        players: [(String, u32); 2],
+    ) {
+        self.play_duel(&controller, notifier, &players).await;
+
+        {
+            let mut duels = self.duels.lock().await;
+            for (user_id, _) in &players {
+                duels.remove(user_id);
+            }
+        }
+
+        while controller.players() > 0 {
+            sleep(CHECK_INTERVAL).await;
+        }
+
+        if let Err(e) = db.clear().await {
+            let (first, second) = (&players[0].0, &players[1].0);
+            log::error!("Failed to clear the duel of {first} and {second}: {e}");
+        }
+    }
+
+    /// Starts the duel once both players are connected and settles it when
+    /// the game ends or one of them has been gone for `RECONNECT_GRACE`.
+    async fn play_duel(
+        &self,
+        controller: &RoomGameController<GD, GSN>,
+        notifier: GSN,
+        players: &[(String, u32); 2],
+    ) {
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        loop {
+            let connected = [
+                controller.is_connected(&players[0].0).await,
+                controller.is_connected(&players[1].0).await,
+            ];
+
+            if connected == [true, true] {
+                break;
+            }
+
+            if Instant::now() >= deadline {
+                self.settle(controller, players, connected, [0, 0]).await;
+                return;
+            }
+
+            sleep(CHECK_INTERVAL).await;
+        }
+
+        // Gives both connections a moment to start waiting for the signal.
+        sleep(CHECK_INTERVAL).await;
+
+        let live = match controller.begin_game().await {
+            Some(live) => live,
+            None => {
+                log::error!("Failed to begin duel");
+                return;
+            }
+        };
+
+        if notifier.send_signal().await.is_err() {
+            log::error!("Failed to signal duel start");
+        }
+
+        let questions = &live.game.questions;
+        let mut dropped_at = None;
+        let connected = loop {
+            let connected = [
+                controller.is_connected(&players[0].0).await,
+                controller.is_connected(&players[1].0).await,
+            ];
+            let now = Instant::now();
+
+            // players still within their grace period when the game ends
+            // are scored like everyone else
+            if now >= live.ends_at() {
+                break [true, true];
+            }
+
+            if connected == [true, true] {
+                dropped_at = None;
+            } else if now - *dropped_at.get_or_insert(now) >= RECONNECT_GRACE {
+                break connected;
+            }
+
+            sleep(CHECK_INTERVAL).await;
+        };
+
+        // Only questions that have been revealed count towards a forfeited
+        // duel.
+        let now = Instant::now();
+        let asked = (0..questions.len())
+            .take_while(|idx| live.question_closes(*idx) <= now)
+            .count();
+
+        let scores = [
+            controller.score(&players[0].0, &live, asked).await,
+            controller.score(&players[1].0, &live, asked).await,
+        ];
+
+        self.settle(controller, players, connected, scores).await;
+    }
+
+    async fn settle(
+        &self,
+        controller: &RoomGameController<GD, GSN>,
+        players: &[(String, u32); 2],
+        connected: [bool; 2],
+        scores: [u32; 2],
+    ) {
+        let forfeit = connected != [true, true];
+        let results = match connected {
+            [false, false] => return,
+            [true, false] => [1.0, 0.0],
+            [false, true] => [0.0, 1.0],
+            [true, true] => match scores[0].cmp(&scores[1]) {
+                std::cmp::Ordering::Greater => [1.0, 0.0],
+                std::cmp::Ordering::Less => [0.0, 1.0],
+                std::cmp::Ordering::Equal => [0.5, 0.5],
+            },
+        };
+
+        for (own, other) in [(0, 1), (1, 0)] {
+            let (user_id, rating) = &players[own];
+            let new_rating = updated_rating(*rating, players[other].1, results[own]);
+
+            if let Err(e) = self.db.set_rating(user_id, new_rating).await {
+                log::error!("Failed to set rating for {user_id}: {e}");
+            }
+
+            let outcome = if results[own] > results[other] {
+                DuelOutcome::Won
+            } else if results[own] < results[other] {
+                DuelOutcome::Lost
+            } else {
+                DuelOutcome::Draw
+            };
+
+            controller
+                .send_to(
+                    user_id,
+                    ServerMessage::DuelResult {
+                        outcome,
+                        forfeit,
+                        score: scores[own],
+                        opponent_score: scores[other],
+                        rating: new_rating,
+                        rating_change: new_rating as i32 - *rating as i32,
+                    },
+                )
+                .await;
+        }
+    }
+}
+
+/// Resolves once the client closes the socket.
+async fn closed<Socket>(ws: &mut Socket)
+where
+    Socket: Stream<Item = Result<Message, warp::Error>> + Unpin,
+{
+    while let Some(Ok(msg)) = ws.next().await {
+        if msg.is_close() {
+            break;
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DuelError {
+    #[error("failed to get data from the database")]
+    DatabaseError,
+    #[error("no questions are available for a duel")]
+    NoGame,
+    #[error("matchmaking was cancelled")]
+    Cancelled,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{match_window, updated_rating, DuelsController, DEFAULT_RATING};
+    use crate::{
+        adapters::{GameMemoryDatabase, Notifier},
+        ports::GameDatabase,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn ratings_move_by_surprise() {
+        assert_eq!(updated_rating(1200, 1200, 1.0), 1216);
+        assert_eq!(updated_rating(1200, 1200, 0.0), 1184);
+        assert_eq!(updated_rating(1200, 1200, 0.5), 1200);
+        assert!(updated_rating(1000, 1400, 1.0) - 1000 > 16);
+        assert_eq!(updated_rating(0, 1200, 0.0), 0);
+    }
+
+    #[test]
+    fn match_window_widens_while_waiting() {
+        assert_eq!(match_window(Duration::ZERO), 100);
+        assert!(match_window(Duration::from_secs(10)) > match_window(Duration::from_secs(1)));
+    }
+
+    #[tokio::test]
+    async fn pairs_waiting_players() {
+        let db = GameMemoryDatabase::default();
+        db.set_rating("b", DEFAULT_RATING + 50).await.unwrap();
+        let controller: DuelsController<_, Notifier> = DuelsController::new(db);
+
+        let (a, b) = tokio::join!(controller.find_match("a"), controller.find_match("b"));
+        let (a, b) = (a.unwrap(), b.unwrap());
+
+        assert_eq!(a.opponent, "b");
+        assert_eq!(b.opponent, "a");
+        assert_eq!(a.opponent_rating, DEFAULT_RATING + 50);
+        assert_eq!(b.opponent_rating, DEFAULT_RATING);
+        assert_eq!(controller.queued().await, 0);
+    }
+}
@@ -1,13 +1,112 @@
 use crate::{
-    models::{AnswerStatus, ClientMessage, ServerMessage},
+    controllers::{
+        answer_distribution, deadline_millis, decode_message, encode_or_error, fifty_fifty, grade,
+        grade_among, now_millis, remaining_lifelines, team_points, used_on, welcome, Chat,
+        ChatError, ChatFilter, CodecError, LifelineError, LiveGame, OptionOrder, Phase,
+        ReactionError, Reactions, Shuffle, TeamError, Teams, LOBBY_DURATION, REACTION_INTERVAL,
+    },
+    models::{
+        Answer, AnswerStatus, ClientMessage, Encoding, ErrorCode, Lifeline, OptionIndex, Question,
+        QuestionKind, QuestionView, ServerMessage, TeamScore, TeamScoring,
+    },
     ports::{GameDatabase, GameStartNotifier, JobSchedular},
 };
-use futures_util::{Sink, Stream, StreamExt, TryStreamExt};
-use std::{sync::Arc, time::Duration};
-use tokio::sync::{mpsc::unbounded_channel, Mutex};
+use futures_util::{future, stream, Sink, SinkExt, Stream, StreamExt};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::{unbounded_channel, UnboundedSender},
+        oneshot, Mutex,
+    },
+    time::{sleep, sleep_until, timeout, Instant},
+};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use warp::ws::Message;
 
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(45);
+/// How long a new connection has to say `Hello` before it is taken to
+/// speak the first protocol version.
+pub const HELLO_TIMEOUT: Duration = Duration::from_secs(1);
+/// Malformed frames a connection may send before it is dropped.
+pub const ERROR_BUDGET: u32 = 10;
+
+/// Why a connection was closed, for the logs.
+#[derive(Error, Debug)]
+enum Disconnect {
+    #[error("client closed the connection")]
+    ClientClosed,
+    #[error("no frames from the client for {0:?}")]
+    IdleTimeout(Duration),
+    #[error("failed to receive from the client: {0}")]
+    ReceiveFailed(warp::Error),
+    #[error("client sent too many malformed messages")]
+    TooManyErrors,
+    #[error("failed to send to the client")]
+    SendFailed,
+    #[error("connected from another session")]
+    Replaced,
+    #[error("client speaks an unsupported protocol")]
+    UnsupportedProtocol,
+    #[error("stopped waiting for games")]
+    GameLoopEnded,
+    #[error("connection task failed")]
+    TaskFailed,
+}
+
+impl Disconnect {
+    /// Whether the client was told why, and should get that message before
+    /// the socket closes.
+    fn has_farewell(&self) -> bool {
+        matches!(
+            self,
+            Disconnect::Replaced | Disconnect::UnsupportedProtocol | Disconnect::TooManyErrors
+        )
+    }
+}
+
+/// What a client settled on before it joined a game: the encoding it reads,
+/// and the message it started with if that was not a `Hello`.
+pub struct Handshake {
+    encoding: Encoding,
+    first_frame: Option<Message>,
+}
+
+impl Handshake {
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+}
+
+/// The socket a player is currently playing from. A newer socket of the
+/// same player takes over and `replaced` tells the old one to close.
+struct Connection {
+    tx: UnboundedSender<ServerMessage>,
+    replaced: oneshot::Sender<()>,
+}
+
+/// The question a player can answer right now. The first answer locks, and
+/// a Second Chance on the question allows one more.
+struct CurrentQuestion {
+    question: Question,
+    key: String,
+    key_prefix: String,
+    options: OptionOrder,
+    answered: bool,
+    second_chance_spent: bool,
+}
+
 #[derive(Clone)]
 pub struct GameController<GD, JS, GSN>
 where
@@ -18,6 +117,42 @@ where
     db: GD,
     schedular: JS,
     notifier: GSN,
+    live_game: Arc<Mutex<Option<LiveGame>>>,
+    question_stats: Arc<Mutex<HashMap<String, ServerMessage>>>,
+    players: Arc<AtomicU32>,
+    connections: Arc<Mutex<HashMap<String, Connection>>>,
+    /// Spectators get what is broadcast to every player.
+    spectators: Arc<Mutex<Vec<UnboundedSender<ServerMessage>>>>,
+    /// Tells spectators about each game that players begin.
+    game_started: broadcast::Sender<LiveGame>,
+    /// Teams formed for the next game.
+    teams: Arc<Mutex<Teams>>,
+    /// Teams of the game being played.
+    game_teams: Arc<Mutex<Teams>>,
+    team_scoring: TeamScoring,
+    team_leaderboard: Arc<Mutex<Option<ServerMessage>>>,
+    lobby: Duration,
+    shuffle: bool,
+    chat: Arc<Mutex<Chat>>,
+    reactions: Arc<Mutex<Reactions>>,
+    heartbeat: Duration,
+    idle_timeout: Duration,
+}
+
+/// Counts a connection as a connected player for as long as it is alive.
+struct PlayerGuard(Arc<AtomicU32>);
+
+impl PlayerGuard {
+    fn new(players: Arc<AtomicU32>) -> Self {
+        players.fetch_add(1, Ordering::SeqCst);
+        Self(players)
+    }
+}
+
+impl Drop for PlayerGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl<GD, JS, GSN> GameController<GD, JS, GSN>
@@ -31,10 +166,90 @@ where
             db,
             schedular,
             notifier,
+            live_game: Arc::new(Mutex::new(None)),
+            question_stats: Arc::new(Mutex::new(HashMap::new())),
+            players: Arc::new(AtomicU32::new(0)),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            spectators: Arc::new(Mutex::new(Vec::new())),
+            game_started: broadcast::channel(1).0,
+            teams: Arc::new(Mutex::new(Teams::default())),
+            game_teams: Arc::new(Mutex::new(Teams::default())),
+            team_scoring: TeamScoring::default(),
+            team_leaderboard: Arc::new(Mutex::new(None)),
+            lobby: LOBBY_DURATION,
+            shuffle: false,
+            chat: Arc::new(Mutex::new(Chat::default())),
+            reactions: Arc::new(Mutex::new(Reactions::default())),
+            heartbeat: HEARTBEAT_INTERVAL,
+            idle_timeout: IDLE_TIMEOUT,
         }
     }
 
-    pub async fn start<Socket>(mut self, user_id: String, ws: Socket)
+    pub fn with_team_scoring(mut self, team_scoring: TeamScoring) -> Self {
+        self.team_scoring = team_scoring;
+        self
+    }
+
+    /// How long players wait between the start signal and the first
+    /// question.
+    pub fn with_lobby(mut self, lobby: Duration) -> Self {
+        self.lobby = lobby;
+        self
+    }
+
+    /// Gives every player their own order of options. Questions keep their
+    /// order so that everyone is on the same question, which the shared
+    /// stats and teammate answers rely on.
+    pub fn with_shuffle(mut self, shuffle: bool) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    /// Pings every connection each `interval` and drops those that send no
+    /// frame, pongs included, for `idle_timeout`.
+    pub fn with_heartbeat(mut self, interval: Duration, idle_timeout: Duration) -> Self {
+        self.heartbeat = interval;
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    pub fn with_chat_filter(mut self, filter: ChatFilter) -> Self {
+        self.chat = Arc::new(Mutex::new(Chat::new(filter)));
+        self
+    }
+
+    /// Lets the player mute others and delete messages in the lobby chat.
+    pub async fn add_moderator(&self, user_id: &str) {
+        self.chat.lock().await.add_moderator(user_id);
+    }
+
+    /// Each game that starts from now on, once it is the live game.
+    pub fn game_starts(&self) -> broadcast::Receiver<LiveGame> {
+        self.game_started.subscribe()
+    }
+
+    /// Number of players currently connected to this controller.
+    pub fn players(&self) -> u32 {
+        self.players.load(Ordering::SeqCst)
+    }
+
+    /// Tells a connection that joined between games when the next one starts.
+    async fn waiting_message(&mut self) -> Result<ServerMessage, JS::Error> {
+        let time = self.schedular.time_till_game().await?;
+        Ok(match time {
+            Some(time) => ServerMessage::TimeTillGame {
+                time: time.as_secs(),
+                sent_at: 0,
+                deadline: deadline_millis(Instant::now() + time),
+            },
+            None => ServerMessage::WaitingForHost,
+        })
+    }
+
+    /// Streams the game to a connection that watches without playing. It is
+    /// not counted as a player, gets no personal results and never starts a
+    /// game itself.
+    pub async fn spectate<Socket>(mut self, ws: Socket)
     where
         Socket: Stream<Item = Result<Message, warp::Error>> + Sink<Message> + Send + 'static,
     {
@@ -42,117 +257,448 @@ where
         let (tx, rx) = unbounded_channel::<ServerMessage>();
         let rx = UnboundedReceiverStream::new(rx);
 
-        let time = self
-            .schedular
-            .time_till_game()
+        let (encoding, first_frame) = match handshake(&mut incoming, &tx, self.idle_timeout).await {
+            Ok(handshake) => handshake,
+            Err(reason) => {
+                log::info!("Spectator disconnected: {reason}");
+                drop(tx);
+                farewell(rx, outgoing, self.heartbeat, &reason).await;
+                return;
+            }
+        };
+
+        let mut game_started = self.game_started.subscribe();
+        let live = self.live_game().await;
+        let first_message = match &live {
+            Some(live) => game_start(live),
+            None => match self.waiting_message().await {
+                Ok(msg) => msg,
+                Err(e) => {
+                    log::error!("Failed to get time till game to spectator: {e}");
+                    return;
+                }
+            },
+        };
+
+        if let Err(e) = tx.send(first_message) {
+            log::error!("Failed to send first message to spectator: {e}");
+            return;
+        }
+        self.spectators.lock().await.push(tx.clone());
+        let spectators = self.spectators.clone();
+        let own_tx = tx.clone();
+
+        let tx_clone = tx.clone();
+        let idle_timeout = self.idle_timeout;
+        let mut receive_from_client = tokio::spawn(async move {
+            let mut budget = ErrorBudget::default();
+            let mut first_frame = first_frame;
+            loop {
+                let msg = match first_frame.take() {
+                    Some(msg) => msg,
+                    None => match receive(&mut incoming, idle_timeout).await {
+                        Ok(msg) => msg,
+                        Err(reason) => break reason,
+                    },
+                };
+
+                let reply = match decode_message(&msg, encoding) {
+                    Ok(ClientMessage::Hello { .. }) => unexpected_hello(),
+                    Ok(ClientMessage::Ping { client_time }) => pong(client_time),
+                    Ok(
+                        ClientMessage::Answer { .. }
+                        | ClientMessage::TextAnswer { .. }
+                        | ClientMessage::NumberAnswer { .. },
+                    ) => ServerMessage::error(ErrorCode::SpectatorOnly, "spectators cannot answer"),
+                    Ok(_) => {
+                        ServerMessage::error(ErrorCode::SpectatorOnly, "spectators can only watch")
+                    }
+                    Err(e) => {
+                        let (reply, within_budget) = budget.spend(e);
+                        if !within_budget {
+                            let _ = tx_clone.send(reply);
+                            break Disconnect::TooManyErrors;
+                        }
+                        reply
+                    }
+                };
+
+                if let Err(e) = tx_clone.send(reply) {
+                    log::error!("Failed to send error message to spectator: {e}");
+                }
+            }
+        });
+
+        let send_to_client = outgoing_messages(rx, self.heartbeat, Encoding::default())
+            .map(Ok)
+            .forward(outgoing);
+        tokio::pin!(send_to_client);
+
+        let mut watch_games = tokio::spawn(async move {
+            if let Some(live) = live {
+                self.watch(&live, &tx).await;
+            }
+
+            loop {
+                let live = match game_started.recv().await {
+                    Ok(live) => live,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+
+                if let Err(e) = tx.send(game_start(&live)) {
+                    log::error!("Failed to send game start message to spectator: {e}");
+                    break;
+                }
+
+                self.watch(&live, &tx).await;
+            }
+        });
+
+        let reason = tokio::select! {
+            reason = &mut receive_from_client => reason.unwrap_or(Disconnect::TaskFailed),
+            _ = &mut watch_games => Disconnect::GameLoopEnded,
+            _ = &mut send_to_client => Disconnect::SendFailed,
+        };
+
+        receive_from_client.abort();
+        watch_games.abort();
+        log::info!("Spectator disconnected: {reason}");
+
+        // Once the aborted tasks and the list drop their senders the channel
+        // closes, so a farewell is flushed and the socket closed.
+        spectators
+            .lock()
             .await
-            .map(|time| time.as_secs());
+            .retain(|spectator| !spectator.same_channel(&own_tx));
+        drop(own_tx);
 
-        let time = match time {
-            Ok(time) => time,
-            Err(e) => {
-                log::error!("Failed to get time till game to {user_id}: {e}");
+        if reason.has_farewell() && timeout(FLUSH_TIMEOUT, send_to_client).await.is_err() {
+            log::error!("Failed to close spectator connection");
+        }
+    }
+
+    pub async fn start<Socket>(self, user_id: String, ws: Socket)
+    where
+        Socket: Stream<Item = Result<Message, warp::Error>> + Sink<Message> + Send + 'static,
+    {
+        self.join(user_id, ws, None).await;
+    }
+
+    /// Like `start`, for a socket whose handshake has already run, see
+    /// [`greet_socket`].
+    pub async fn start_greeted<Socket>(self, user_id: String, ws: Socket, greeted: Handshake)
+    where
+        Socket: Stream<Item = Result<Message, warp::Error>> + Sink<Message> + Send + 'static,
+    {
+        self.join(user_id, ws, Some(greeted)).await;
+    }
+
+    async fn join<Socket>(mut self, user_id: String, ws: Socket, greeted: Option<Handshake>)
+    where
+        Socket: Stream<Item = Result<Message, warp::Error>> + Sink<Message> + Send + 'static,
+    {
+        let _player = PlayerGuard::new(self.players.clone());
+        let (outgoing, mut incoming) = ws.split();
+        let (tx, rx) = unbounded_channel::<ServerMessage>();
+        let rx = UnboundedReceiverStream::new(rx);
+
+        // A welcome sent before joining is not in the channel to switch the
+        // encoding, so the socket starts out in the negotiated one.
+        let greeted_encoding = greeted
+            .as_ref()
+            .map(Handshake::encoding)
+            .unwrap_or_default();
+        let handshake = match greeted {
+            Some(greeted) => Ok((greeted.encoding, greeted.first_frame)),
+            None => handshake(&mut incoming, &tx, self.idle_timeout).await,
+        };
+        let (encoding, first_frame) = match handshake {
+            Ok(handshake) => handshake,
+            Err(reason) => {
+                log::info!("{user_id} disconnected: {reason}");
+                drop(tx);
+                farewell(rx, outgoing, self.heartbeat, &reason).await;
                 return;
             }
         };
 
-        match tx.send(ServerMessage::TimeTillGame { time }) {
+        let live = self.live_game().await;
+
+        let first_message = match &live {
+            Some(live) => match self.resume(&user_id, live).await {
+                Ok(resume) => resume,
+                Err(e) => {
+                    log::error!("Failed to get resume snapshot for {user_id}: {e}");
+                    return;
+                }
+            },
+            None => match self.waiting_message().await {
+                Ok(msg) => msg,
+                Err(e) => {
+                    log::error!("Failed to get time till game to {user_id}: {e}");
+                    return;
+                }
+            },
+        };
+
+        match tx.send(first_message) {
             Ok(_) => (),
             Err(e) => {
-                log::error!("Failed to send time till game to {user_id}: {e}");
+                log::error!("Failed to send first message to {user_id}: {e}");
                 return;
             }
         };
 
-        let current_question: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+        let (replaced, mut replaced_rx) = oneshot::channel();
+        let previous = self.connections.lock().await.insert(
+            user_id.clone(),
+            Connection {
+                tx: tx.clone(),
+                replaced,
+            },
+        );
+
+        if let Some(previous) = previous {
+            log::info!("{user_id} connected again, closing their previous connection");
+            let _ = previous.tx.send(ServerMessage::error(
+                ErrorCode::SessionReplaced,
+                "connected from another session",
+            ));
+            let _ = previous.replaced.send(());
+        }
+
+        let current_question: Arc<Mutex<Option<CurrentQuestion>>> = Arc::new(Mutex::new(None));
+
+        let connections = self.connections.clone();
+        let own_tx = tx.clone();
+        let user_id_end = user_id.clone();
 
         let this_clone = self.clone();
         let current_question_clone = current_question.clone();
         let tx_clone = tx.clone();
         let user_id_clone = user_id.clone();
-        let receive_from_client = tokio::spawn(async move {
-            while let Ok(msg) = incoming.try_next().await {
-                let msg = match msg {
+        let idle_timeout = self.idle_timeout;
+        let mut receive_from_client = tokio::spawn(async move {
+            let mut budget = ErrorBudget::default();
+            let mut first_frame = first_frame;
+            loop {
+                let msg = match first_frame.take() {
                     Some(msg) => msg,
-                    None => {
-                        log::error!("Failed to receive message from {user_id_clone}");
-                        break;
-                    }
+                    None => match receive(&mut incoming, idle_timeout).await {
+                        Ok(msg) => msg,
+                        Err(reason) => break reason,
+                    },
                 };
 
-                let msg = match msg.to_str() {
+                let msg = match decode_message(&msg, encoding) {
                     Ok(msg) => msg,
-                    Err(_) => {
-                        log::error!("Failed to get message from {user_id_clone}");
-                        break;
+                    Err(e) => {
+                        log::warn!("Failed to parse message from {user_id_clone}: {e}");
+                        let (reply, within_budget) = budget.spend(e);
+                        if let Err(e) = tx_clone.send(reply) {
+                            log::error!("Failed to send error message to {user_id_clone}: {e}");
+                        }
+                        if !within_budget {
+                            break Disconnect::TooManyErrors;
+                        }
+                        continue;
                     }
                 };
 
-                let msg = match serde_json::from_str::<ClientMessage>(msg) {
-                    Ok(msg) => msg,
-                    Err(e) => {
-                        log::error!("Failed to parse message from {user_id_clone}: {e}");
-                        break;
+                let answer = match &msg {
+                    ClientMessage::Answer { answer_idx } => Answer::Choice(answer_idx.clone()),
+                    ClientMessage::TextAnswer { answer } => Answer::Text(answer.clone()),
+                    ClientMessage::NumberAnswer { answer } => Answer::Number(*answer),
+                    ClientMessage::UseLifeline { lifeline } => {
+                        let question =
+                            current_question_clone.lock().await.as_ref().map(|current| {
+                                (
+                                    current.question.clone(),
+                                    current.key.clone(),
+                                    current.key_prefix.clone(),
+                                    current.options.clone(),
+                                )
+                            });
+
+                        let replies = match question {
+                            Some((question, key, key_prefix, options)) => this_clone
+                                .use_lifeline(
+                                    &user_id_clone,
+                                    &question,
+                                    &key,
+                                    &key_prefix,
+                                    &options,
+                                    *lifeline,
+                                )
+                                .await
+                                .unwrap_or_else(|e| {
+                                    vec![ServerMessage::error(ErrorCode::Lifeline, e.to_string())]
+                                }),
+                            None => vec![ServerMessage::error(
+                                ErrorCode::Lifeline,
+                                LifelineError::NoQuestion.to_string(),
+                            )],
+                        };
+
+                        for reply in replies {
+                            if let Err(e) = tx_clone.send(reply) {
+                                log::error!(
+                                    "Failed to send lifeline reply to {user_id_clone}: {e}"
+                                );
+                            }
+                        }
+                        continue;
+                    }
+                    ClientMessage::Hello { .. } => {
+                        if let Err(e) = tx_clone.send(unexpected_hello()) {
+                            log::error!("Failed to send error message to {user_id_clone}: {e}");
+                        }
+                        continue;
+                    }
+                    ClientMessage::Ping { client_time } => {
+                        if let Err(e) = tx_clone.send(pong(*client_time)) {
+                            log::error!("Failed to send pong to {user_id_clone}: {e}");
+                        }
+                        continue;
+                    }
+                    ClientMessage::React { emoji } => {
+                        if let Err(e) = this_clone.react(&user_id_clone, emoji).await {
+                            if let Err(e) = tx_clone
+                                .send(ServerMessage::error(ErrorCode::Reaction, e.to_string()))
+                            {
+                                log::error!(
+                                    "Failed to send reaction error to {user_id_clone}: {e}"
+                                );
+                            }
+                        }
+                        continue;
+                    }
+                    ClientMessage::Chat { .. }
+                    | ClientMessage::MuteChat { .. }
+                    | ClientMessage::DeleteChat { .. } => {
+                        if let Err(e) = this_clone.chat(&user_id_clone, msg).await {
+                            if let Err(e) =
+                                tx_clone.send(ServerMessage::error(ErrorCode::Chat, e.to_string()))
+                            {
+                                log::error!("Failed to send chat error to {user_id_clone}: {e}");
+                            }
+                        }
+                        continue;
+                    }
+                    ClientMessage::CreateTeam { .. }
+                    | ClientMessage::JoinTeam { .. }
+                    | ClientMessage::LeaveTeam => {
+                        if let Err(e) = this_clone.change_team(&user_id_clone, msg).await {
+                            if let Err(e) =
+                                tx_clone.send(ServerMessage::error(ErrorCode::Team, e.to_string()))
+                            {
+                                log::error!("Failed to send team error to {user_id_clone}: {e}");
+                            }
+                        }
+                        continue;
                     }
                 };
 
-                match msg {
-                    ClientMessage::Answer { answer_idx } => {
-                        match &*current_question_clone.lock().await {
-                            Some(question) => {
-                                match this_clone
-                                    .db
-                                    .set_answer(&user_id_clone, question, answer_idx)
-                                    .await
-                                {
-                                    Ok(()) => (),
-                                    Err(e) => {
-                                        log::error!(
-                                            "Failed to set answer for {user_id_clone}: {e}"
-                                        );
-                                        continue;
-                                    }
+                match &mut *current_question_clone.lock().await {
+                    Some(current) => {
+                        let answer = current.options.answer_to_canonical(answer);
+                        if !current.question.accepts(&answer) {
+                            if let Err(e) = tx_clone.send(ServerMessage::error(
+                                ErrorCode::InvalidAnswer,
+                                "answer does not fit the current question",
+                            )) {
+                                log::error!("Failed to send error message to {user_id_clone}: {e}");
+                            }
+                            continue;
+                        }
+
+                        let question = &current.question;
+                        let second_chance = match this_clone.db.get_lifelines(&user_id_clone).await
+                        {
+                            Ok(used) => used_on(&used, &current.key, Lifeline::SecondChance),
+                            Err(e) => {
+                                log::error!("Failed to get lifelines for {user_id_clone}: {e}");
+                                false
+                            }
+                        };
+
+                        if current.answered {
+                            if !second_chance || current.second_chance_spent {
+                                if let Err(e) = tx_clone.send(ServerMessage::error(
+                                    ErrorCode::AnswerLocked,
+                                    "the answer to this question is locked",
+                                )) {
+                                    log::error!(
+                                        "Failed to send error message to {user_id_clone}: {e}"
+                                    );
                                 }
+                                continue;
+                            }
+                            current.second_chance_spent = true;
+                        }
+
+                        match this_clone
+                            .db
+                            .set_answer(&user_id_clone, &current.key, answer.clone())
+                            .await
+                        {
+                            Ok(()) => (),
+                            Err(e) => {
+                                log::error!("Failed to set answer for {user_id_clone}: {e}");
+                                continue;
                             }
-                            None => {
-                                match tx_clone.send(ServerMessage::NoGame) {
-                                    Ok(()) => (),
-                                    Err(e) => {
-                                        log::error!("Failed to send no game message to {user_id_clone}: {e}");
-                                        continue;
-                                    }
+                        }
+
+                        this_clone
+                            .send_teammate_answer(&user_id_clone, question, answer)
+                            .await;
+
+                        // acknowledged whether or not the answer was right, so
+                        // it gives nothing away
+                        if second_chance && !current.answered {
+                            match tx_clone.send(ServerMessage::SecondChance) {
+                                Ok(()) => (),
+                                Err(e) => {
+                                    log::error!(
+                                        "Failed to send second chance to {user_id_clone}: {e}"
+                                    );
                                 }
                             }
                         }
+                        current.answered = true;
                     }
-                };
+                    None => match tx_clone.send(ServerMessage::NoGame) {
+                        Ok(()) => (),
+                        Err(e) => {
+                            log::error!("Failed to send no game message to {user_id_clone}: {e}");
+                            continue;
+                        }
+                    },
+                }
             }
         });
 
-        let send_to_client = rx
-            .map(|msg| {
-                let msg = serde_json::to_string(&msg);
-
-                match msg {
-                    Ok(msg) => Message::text(msg),
-                    Err(_) => Message::text("MESSAGE_SERIALIZATION_ERROR"),
-                }
-            })
+        let send_to_client = outgoing_messages(rx, self.heartbeat, greeted_encoding)
             .map(Ok)
             .forward(outgoing);
+        tokio::pin!(send_to_client);
+
+        let mut wait_for_game_to_start = tokio::spawn(async move {
+            if let Some(live) = live {
+                self.play(&live, &tx, &current_question, &user_id).await;
+            }
 
-        let wait_for_game_to_start = tokio::spawn(async move {
             while let Some(()) = self.notifier.wait_for_signal().await {
-                let game = self.db.get_game().await;
-                let game = match game {
-                    Ok(Some(game)) => game,
-                    _ => {
+                let live = match self.begin_game().await {
+                    Some(live) => live,
+                    None => {
                         log::error!("Failed to get game");
                         break;
                     }
                 };
 
-                match tx.send(ServerMessage::GameStart) {
+                match tx.send(game_start(&live)) {
                     Ok(()) => (),
                     Err(e) => {
                         log::error!("Failed to send game start message to {user_id}: {e}");
@@ -160,102 +706,1355 @@ where
                     }
                 };
 
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                self.play(&live, &tx, &current_question, &user_id).await;
+            }
+        });
 
-                for question in game.questions.into_iter() {
-                    match tx.send(ServerMessage::Question {
-                        question: question.question.clone(),
-                        options: question.options,
-                    }) {
-                        Ok(()) => (),
-                        Err(e) => {
-                            log::error!("Failed to send question to {user_id}: {e}");
-                            continue;
-                        }
-                    };
+        let reason = tokio::select! {
+            reason = &mut receive_from_client => reason.unwrap_or(Disconnect::TaskFailed),
+            _ = &mut wait_for_game_to_start => Disconnect::GameLoopEnded,
+            _ = &mut send_to_client => Disconnect::SendFailed,
+            _ = &mut replaced_rx => Disconnect::Replaced,
+        };
 
-                    *current_question.lock().await = Some(question.question.clone());
+        {
+            let mut connections = connections.lock().await;
+            if connections
+                .get(&user_id_end)
+                .is_some_and(|connection| connection.tx.same_channel(&own_tx))
+            {
+                connections.remove(&user_id_end);
+            }
+        }
 
-                    tokio::time::sleep(Duration::from_secs(10)).await;
+        // Nothing of the connection may outlive it, otherwise a silent client
+        // would keep its tasks and channel around for good.
+        receive_from_client.abort();
+        wait_for_game_to_start.abort();
+        log::info!("{user_id_end} disconnected: {reason}");
 
-                    let answer = self.db.get_answer(&user_id, &question.question).await;
-                    let answer = match answer {
-                        Ok(answer) => answer,
-                        Err(e) => {
-                            log::error!("Failed to get answer for {user_id}: {e}");
-                            continue;
-                        }
-                    };
-
-                    let answer_status = match answer {
-                        Some(answer) => {
-                            if answer == question.answer_idx {
-                                AnswerStatus::Correct
-                            } else {
-                                AnswerStatus::Incorrect
-                            }
-                        }
-                        None => AnswerStatus::NoAnswer,
-                    };
-
-                    match self
-                        .db
-                        .set_answer_status(&user_id, &question.question, &answer_status)
-                        .await
-                    {
-                        Ok(_) => (),
-                        Err(e) => {
-                            log::error!("Failed to set answer status for {user_id}: {e}");
-                            continue;
-                        }
-                    };
+        if reason.has_farewell() {
+            // Once the aborted tasks drop their senders the channel closes,
+            // so this flushes the error and closes the socket.
+            drop(own_tx);
+            if timeout(FLUSH_TIMEOUT, send_to_client).await.is_err() {
+                log::error!("Failed to close connection of {user_id_end}");
+            }
+        }
+    }
+}
 
-                    match tx.send(ServerMessage::Answer {
-                        status: answer_status,
-                        answer_idx: question.answer_idx,
-                    }) {
-                        Ok(_) => (),
-                        Err(e) => {
-                            log::error!("Failed to send answer for {user_id}: {e}");
-                            continue;
-                        }
-                    };
+impl<GD, JS, GSN> GameController<GD, JS, GSN>
+where
+    GD: GameDatabase,
+    JS: JobSchedular,
+    GSN: GameStartNotifier,
+{
+    /// Returns the game that is currently being played, if any.
+    async fn live_game(&self) -> Option<LiveGame> {
+        let live_game = self.live_game.lock().await;
+        live_game
+            .as_ref()
+            .filter(|live| live.phase(Instant::now()) != Phase::Ended)
+            .cloned()
+    }
 
-                    tokio::time::sleep(Duration::from_secs(10)).await;
-                }
+    /// Starts a new game, or joins the one another connection has just
+    /// started for the same signal.
+    pub async fn begin_game(&self) -> Option<LiveGame> {
+        let mut live_game = self.live_game.lock().await;
+        let now = Instant::now();
 
-                let score = self
-                    .db
-                    .get_answers_statuses(&user_id)
-                    .await
-                    .unwrap_or_default()
-                    .iter()
-                    .filter(|x| **x == AnswerStatus::Correct)
-                    .count() as u32;
+        if let Some(live) = live_game.as_ref() {
+            if live.phase(now) == Phase::Lobby {
+                return Some(live.clone());
+            }
+        }
+
+        let game = match self.db.get_game().await {
+            Ok(Some(game)) => game,
+            Ok(None) => return None,
+            Err(e) => {
+                log::error!("Failed to get game from the database: {e}");
+                return None;
+            }
+        };
+
+        if let Err(e) = game.validate() {
+            log::error!("Failed to validate game: {e}");
+            return None;
+        }
+
+        let live = LiveGame::new(game, now).with_lobby(self.lobby);
+        *live_game = Some(live.clone());
+        // fails only when nobody is listening
+        let _ = self.game_started.send(live.clone());
+        self.question_stats.lock().await.clear();
+        *self.team_leaderboard.lock().await = None;
+        // teams play one game, the next is formed from scratch
+        *self.game_teams.lock().await = std::mem::take(&mut *self.teams.lock().await);
+        Some(live)
+    }
+
+    async fn play(
+        &self,
+        live: &LiveGame,
+        tx: &UnboundedSender<ServerMessage>,
+        current_question: &Mutex<Option<CurrentQuestion>>,
+        user_id: &str,
+    ) {
+        let questions = &live.game.questions;
+        let (start, resumed_mid_question) = match live.phase(Instant::now()) {
+            Phase::Lobby => (0, false),
+            Phase::Question(idx) => (idx, true),
+            Phase::Reveal(idx) => (idx + 1, false),
+            Phase::Ended => return,
+        };
+
+        if let (0, false, Some(question)) = (start, resumed_mid_question, questions.first()) {
+            preload(tx, question, user_id);
+        }
+
+        for (idx, question) in questions.iter().enumerate().skip(start) {
+            sleep_until(live.question_opens(idx)).await;
+            let options = self.option_order(live, user_id, question);
 
-                match self.db.set_score(&user_id, score).await {
-                    Ok(_) => (),
+            if !(resumed_mid_question && idx == start) {
+                let view = options.view(question);
+                match tx.send(question_message(view, live.question_closes(idx))) {
+                    Ok(()) => (),
                     Err(e) => {
-                        log::error!("Failed to set score for {user_id}: {e}");
+                        log::error!("Failed to send question to {user_id}: {e}");
                         continue;
                     }
                 };
+            }
 
-                match tx.send(ServerMessage::GameEnd { score }) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        log::error!("Failed to send game end message to {user_id}: {e}");
-                        continue;
+            // a player who answered before reconnecting keeps the answer, and
+            // cannot tell whether a Second Chance was already taken
+            let key = live.question_key(idx);
+            let answered = resumed_mid_question
+                && idx == start
+                && !matches!(self.db.get_answer(user_id, &key).await, Ok(None));
+            *current_question.lock().await = Some(CurrentQuestion {
+                question: question.clone(),
+                key,
+                key_prefix: live.key_prefix(),
+                options: options.clone(),
+                answered,
+                second_chance_spent: answered,
+            });
+
+            sleep_until(live.question_closes(idx)).await;
+            *current_question.lock().await = None;
+
+            let (answer_status, answer) = match self.grade_answer(user_id, live, idx).await {
+                Ok(graded) => graded,
+                Err(e) => {
+                    log::error!("Failed to grade answer for {user_id}: {e}");
+                    continue;
+                }
+            };
+
+            let reveal_ends = live.phase_ends(Phase::Reveal(idx));
+            match tx.send(reveal(
+                question,
+                &options,
+                answer_status,
+                answer,
+                reveal_ends,
+            )) {
+                Ok(_) => (),
+                Err(e) => {
+                    log::error!("Failed to send answer for {user_id}: {e}");
+                    continue;
+                }
+            };
+
+            match self.question_stats(live, idx).await {
+                Ok(mut stats) => {
+                    if let ServerMessage::QuestionStats { counts, .. } = &mut stats {
+                        *counts = options.arrange(counts);
                     }
-                };
+
+                    if let Err(e) = tx.send(stats) {
+                        log::error!("Failed to send question stats to {user_id}: {e}");
+                    }
+                }
+                Err(e) => log::error!("Failed to get question stats: {e}"),
             }
-        });
 
-        tokio::select! {
-            _ = receive_from_client => {}
-            _ = wait_for_game_to_start => {}
-            _ = send_to_client => {},
+            if let Some(question) = questions.get(idx + 1) {
+                preload(tx, question, user_id);
+            }
+        }
+
+        sleep_until(live.ends_at()).await;
+
+        let score = self.score(user_id, live, questions.len()).await;
+
+        match self.db.set_score(user_id, score).await {
+            Ok(_) => (),
+            Err(e) => {
+                log::error!("Failed to set score for {user_id}: {e}");
+                return;
+            }
+        };
+
+        if let Err(e) = self.db.clear_lifelines(user_id).await {
+            log::error!("Failed to clear lifelines for {user_id}: {e}");
+        }
+
+        match tx.send(ServerMessage::GameEnd { score }) {
+            Ok(_) => (),
+            Err(e) => {
+                log::error!("Failed to send game end message to {user_id}: {e}");
+                return;
+            }
+        };
+
+        if let Some(leaderboard) = self.team_leaderboard(live).await {
+            if let Err(e) = tx.send(leaderboard) {
+                log::error!("Failed to send team leaderboard to {user_id}: {e}");
+            }
+        }
+    }
+
+    /// The spectator's view of the game: questions, revealed answers, stats
+    /// and the team leaderboard.
+    async fn watch(&self, live: &LiveGame, tx: &UnboundedSender<ServerMessage>) {
+        let questions = &live.game.questions;
+        let start = match live.phase(Instant::now()) {
+            Phase::Lobby => 0,
+            Phase::Question(idx) => idx,
+            Phase::Reveal(idx) => idx + 1,
+            Phase::Ended => return,
         };
+
+        for (idx, question) in questions.iter().enumerate().skip(start) {
+            sleep_until(live.question_opens(idx)).await;
+            if let Err(e) = tx.send(question_message(question.into(), live.question_closes(idx))) {
+                log::error!("Failed to send question to spectator: {e}");
+                return;
+            }
+
+            sleep_until(live.question_closes(idx)).await;
+            if let Err(e) = tx.send(ServerMessage::Reveal {
+                answer: question.answer(),
+                sent_at: 0,
+                deadline: deadline_millis(live.phase_ends(Phase::Reveal(idx))),
+            }) {
+                log::error!("Failed to send reveal to spectator: {e}");
+                return;
+            }
+
+            match self.question_stats(live, idx).await {
+                Ok(stats) => {
+                    if let Err(e) = tx.send(stats) {
+                        log::error!("Failed to send question stats to spectator: {e}");
+                    }
+                }
+                Err(e) => log::error!("Failed to get question stats: {e}"),
+            }
+        }
+
+        sleep_until(live.ends_at()).await;
+
+        if let Some(leaderboard) = self.team_leaderboard(live).await {
+            if let Err(e) = tx.send(leaderboard) {
+                log::error!("Failed to send team leaderboard to spectator: {e}");
+            }
+        }
+    }
+
+    async fn change_team(&self, user_id: &str, msg: ClientMessage) -> Result<(), TeamError> {
+        if self.live_game().await.is_some() {
+            return Err(TeamError::GameInProgress);
+        }
+
+        let mut teams = self.teams.lock().await;
+        let previous = teams.team_of(user_id).map(String::from);
+
+        match msg {
+            ClientMessage::CreateTeam { name } => teams.create(&name, user_id)?,
+            ClientMessage::JoinTeam { name } => teams.join(&name, user_id)?,
+            _ => {
+                teams.leave(user_id);
+            }
+        }
+
+        let current = teams.team_of(user_id).map(String::from);
+        let mut notify = vec![(user_id.to_string(), current.clone())];
+
+        for name in previous.iter().chain(current.iter()) {
+            for member in teams.members(name) {
+                if member != user_id {
+                    notify.push((member.clone(), Some(name.clone())));
+                }
+            }
+        }
+
+        for (member, name) in notify {
+            let members = name
+                .as_deref()
+                .map(|name| teams.members(name).to_vec())
+                .unwrap_or_default();
+            self.send_to(&member, ServerMessage::Team { name, members })
+                .await;
+        }
+
+        Ok(())
+    }
+
+    async fn chat(&self, user_id: &str, msg: ClientMessage) -> Result<(), ChatError> {
+        let now = Instant::now();
+        let question_open = self
+            .live_game()
+            .await
+            .is_some_and(|live| matches!(live.phase(now), Phase::Question(_)));
+
+        let broadcast = {
+            let mut chat = self.chat.lock().await;
+            match msg {
+                ClientMessage::Chat { .. } if question_open => return Err(ChatError::ReadOnly),
+                ClientMessage::Chat { text } => chat.post(user_id, &text, now)?,
+                ClientMessage::MuteChat { user_id: muted } => chat.mute(user_id, &muted)?,
+                ClientMessage::DeleteChat { id } => chat.delete(user_id, id)?,
+                _ => return Ok(()),
+            }
+        };
+
+        self.broadcast(broadcast).await;
+        Ok(())
+    }
+
+    /// Counts a reaction towards the next batch, scheduling the batch's
+    /// broadcast if it is the first reaction since the last one.
+    async fn react(&self, user_id: &str, emoji: &str) -> Result<(), ReactionError> {
+        let revealing = self
+            .live_game()
+            .await
+            .is_some_and(|live| matches!(live.phase(Instant::now()), Phase::Reveal(_)));
+
+        if !revealing {
+            return Err(ReactionError::NotRevealing);
+        }
+
+        if self.reactions.lock().await.add(user_id, emoji)? {
+            let reactions = self.reactions.clone();
+            let connections = self.connections.clone();
+            let spectators = self.spectators.clone();
+
+            tokio::spawn(async move {
+                sleep(REACTION_INTERVAL).await;
+                let batch = reactions.lock().await.take();
+                if let Some(batch) = batch {
+                    broadcast(&connections, &spectators, batch).await;
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    fn shuffle(&self, live: &LiveGame, user_id: &str) -> Option<Shuffle> {
+        self.shuffle.then(|| Shuffle::new(&live.id, user_id))
+    }
+
+    fn option_order(&self, live: &LiveGame, user_id: &str, question: &Question) -> OptionOrder {
+        self.shuffle(live, user_id)
+            .map(|shuffle| shuffle.option_order(question))
+            .unwrap_or_default()
+    }
+
+    /// Total points of a player over the given questions.
+    pub async fn score(&self, user_id: &str, live: &LiveGame, asked: usize) -> u32 {
+        self.answer_statuses(user_id, live, asked)
+            .await
+            .iter()
+            .map(AnswerStatus::points)
+            .sum()
+    }
+
+    /// Graded answers of a player to the first `asked` questions of the game,
+    /// skipping any that could not be read.
+    pub async fn answer_statuses(
+        &self,
+        user_id: &str,
+        live: &LiveGame,
+        asked: usize,
+    ) -> Vec<AnswerStatus> {
+        let mut statuses = Vec::with_capacity(asked);
+        for idx in 0..asked {
+            match self.answer_status(user_id, live, idx).await {
+                Ok(answer_status) => statuses.push(answer_status),
+                Err(e) => log::error!("Failed to get answer status for {user_id}: {e}"),
+            }
+        }
+        statuses
+    }
+
+    pub async fn is_connected(&self, user_id: &str) -> bool {
+        self.connections.lock().await.contains_key(user_id)
+    }
+
+    pub async fn send_to(&self, user_id: &str, msg: ServerMessage) {
+        if let Some(connection) = self.connections.lock().await.get(user_id) {
+            if let Err(e) = connection.tx.send(msg) {
+                log::error!("Failed to send message to {user_id}: {e}");
+            }
+        }
+    }
+
+    pub async fn broadcast(&self, msg: ServerMessage) {
+        broadcast(&self.connections, &self.spectators, msg).await;
+    }
+
+    /// Shares a player's answer with their team, with choices in the order
+    /// each teammate sees them.
+    async fn send_teammate_answer(&self, user_id: &str, question: &Question, answer: Answer) {
+        let live = self.live_game().await;
+        let teammates: Vec<String> = {
+            let teams = self.game_teams.lock().await;
+            match teams.team_of(user_id) {
+                Some(name) => teams
+                    .members(name)
+                    .iter()
+                    .filter(|member| *member != user_id)
+                    .cloned()
+                    .collect(),
+                None => return,
+            }
+        };
+
+        for teammate in teammates {
+            let answer = match &live {
+                Some(live) => self
+                    .option_order(live, &teammate, question)
+                    .answer_to_shown(answer.clone()),
+                None => answer.clone(),
+            };
+
+            self.send_to(
+                &teammate,
+                ServerMessage::TeammateAnswer {
+                    user_id: user_id.into(),
+                    answer,
+                },
+            )
+            .await;
+        }
+    }
+
+    /// Ranks the teams by their pooled score. The leaderboard is computed
+    /// once per game and shared by every connection.
+    async fn team_leaderboard(&self, live: &LiveGame) -> Option<ServerMessage> {
+        let mut team_leaderboard = self.team_leaderboard.lock().await;
+
+        if let Some(leaderboard) = team_leaderboard.as_ref() {
+            return Some(leaderboard.clone());
+        }
+
+        let teams = self.game_teams.lock().await.clone();
+        if teams.is_empty() {
+            return None;
+        }
+
+        let mut scores = Vec::new();
+        for (name, members) in teams.iter() {
+            let mut score = 0;
+
+            for idx in 0..live.game.questions.len() {
+                let mut results = Vec::with_capacity(members.len());
+
+                for member in members {
+                    match self.answer_result(member, live, idx).await {
+                        Ok(result) => results.push(result),
+                        Err(e) => log::error!("Failed to get answer status for {member}: {e}"),
+                    }
+                }
+
+                score += team_points(self.team_scoring, &results);
+            }
+
+            scores.push(TeamScore {
+                name: name.clone(),
+                members: members.clone(),
+                score,
+            });
+        }
+
+        scores.sort_by_key(|team| Reverse(team.score));
+
+        let leaderboard = ServerMessage::TeamLeaderboard { teams: scores };
+        *team_leaderboard = Some(leaderboard.clone());
+        Some(leaderboard)
+    }
+
+    /// Totals the answers to the closed question at `idx`. The totals are
+    /// computed once per question and shared by every connection.
+    async fn question_stats(
+        &self,
+        live: &LiveGame,
+        idx: usize,
+    ) -> Result<ServerMessage, GD::Error> {
+        let question = &live.game.questions[idx];
+        let mut question_stats = self.question_stats.lock().await;
+        let key = live.question_key(idx);
+
+        if let Some(stats) = question_stats.get(&key) {
+            return Ok(stats.clone());
+        }
+
+        let answers = self.db.get_answers(&key).await?;
+        let (counts, correct) = answer_distribution(question, &answers);
+        let stats = ServerMessage::QuestionStats {
+            counts,
+            answered: answers.len() as u32,
+            correct,
+            players: self.players.load(Ordering::SeqCst),
+            sent_at: 0,
+            deadline: deadline_millis(live.phase_ends(Phase::Reveal(idx))),
+        };
+
+        question_stats.insert(key, stats.clone());
+        Ok(stats)
+    }
+
+    /// Grades the player's answer to the closed question at `idx` and stores
+    /// the result.
+    async fn grade_answer(
+        &self,
+        user_id: &str,
+        live: &LiveGame,
+        idx: usize,
+    ) -> Result<(AnswerStatus, Option<Answer>), GD::Error> {
+        let question = &live.game.questions[idx];
+        let key = live.question_key(idx);
+        let answer = self.db.get_answer(user_id, &key).await?;
+        let lifelines = self.db.get_lifelines(user_id).await?;
+
+        let answer_status = if used_on(&lifelines, &key, Lifeline::Skip) {
+            AnswerStatus::Skipped
+        } else if let QuestionKind::Number { .. } = question.kind {
+            let answers = self.db.get_answers(&key).await?;
+            grade_among(question, answer.as_ref(), &answers)
+        } else {
+            grade(question, answer.as_ref())
+        };
+
+        self.db
+            .set_answer_status(user_id, &key, &answer_status)
+            .await?;
+
+        Ok((answer_status, answer))
+    }
+
+    /// Looks up the stored result of the closed question at `idx`, grading it
+    /// first if the player was disconnected when it closed.
+    async fn answer_status(
+        &self,
+        user_id: &str,
+        live: &LiveGame,
+        idx: usize,
+    ) -> Result<AnswerStatus, GD::Error> {
+        match self
+            .db
+            .get_answer_status(user_id, &live.question_key(idx))
+            .await?
+        {
+            Some(answer_status) => Ok(answer_status),
+            None => Ok(self.grade_answer(user_id, live, idx).await?.0),
+        }
+    }
+
+    /// The stored result of the closed question at `idx` together with the
+    /// answer it was given for.
+    async fn answer_result(
+        &self,
+        user_id: &str,
+        live: &LiveGame,
+        idx: usize,
+    ) -> Result<(AnswerStatus, Option<Answer>), GD::Error> {
+        let answer_status = self.answer_status(user_id, live, idx).await?;
+        let answer = self.db.get_answer(user_id, &live.question_key(idx)).await?;
+        Ok((answer_status, answer))
+    }
+
+    async fn resume(&self, user_id: &str, live: &LiveGame) -> Result<ServerMessage, GD::Error> {
+        let now = Instant::now();
+        let phase = live.phase(now);
+        let questions = &live.game.questions;
+
+        let (question_index, current, closed) = match phase {
+            Phase::Lobby => (0, None, 0),
+            Phase::Question(idx) => (idx, Some(&questions[idx]), idx),
+            Phase::Reveal(idx) => (idx, Some(&questions[idx]), idx + 1),
+            Phase::Ended => (questions.len(), None, questions.len()),
+        };
+
+        let mut answers = Vec::with_capacity(closed);
+        for idx in 0..closed {
+            answers.push(self.answer_status(user_id, live, idx).await?);
+        }
+
+        let (question, answer) = match current {
+            Some(question) => {
+                let options = self.option_order(live, user_id, question);
+                let answer = self
+                    .db
+                    .get_answer(user_id, &live.question_key(question_index))
+                    .await?;
+                (
+                    Some(options.view(question)),
+                    answer.map(|answer| options.answer_to_shown(answer)),
+                )
+            }
+            None => (None, None),
+        };
+
+        Ok(ServerMessage::Resume {
+            question_index,
+            question,
+            revealing: matches!(phase, Phase::Reveal(_)),
+            time_remaining: live
+                .phase_ends(phase)
+                .saturating_duration_since(now)
+                .as_secs(),
+            answer,
+            score: answers.iter().map(AnswerStatus::points).sum(),
+            answers,
+            sent_at: 0,
+            deadline: deadline_millis(live.phase_ends(phase)),
+        })
+    }
+
+    async fn use_lifeline(
+        &self,
+        user_id: &str,
+        question: &Question,
+        key: &str,
+        key_prefix: &str,
+        options: &OptionOrder,
+        lifeline: Lifeline,
+    ) -> Result<Vec<ServerMessage>, LifelineError> {
+        use LifelineError::*;
+
+        let used = self
+            .db
+            .get_lifelines(user_id)
+            .await
+            .or(Err(DatabaseError))?;
+
+        if used_on(&used, key, lifeline) {
+            return Err(AlreadyUsed);
+        }
+
+        let remaining = remaining_lifelines(&used, key_prefix, lifeline);
+        if remaining == 0 {
+            return Err(NoneLeft);
+        }
+
+        let mut replies = Vec::with_capacity(2);
+        replies.push(ServerMessage::LifelineUsed {
+            lifeline,
+            remaining: remaining - 1,
+        });
+
+        if lifeline == Lifeline::FiftyFifty {
+            let mut hidden: Vec<_> = fifty_fifty(question, &mut rand::thread_rng())
+                .ok_or(NotApplicable)?
+                .iter()
+                .map(|idx| options.to_shown(idx))
+                .collect();
+            hidden.sort_by_key(OptionIndex::index);
+            replies.push(ServerMessage::HiddenOptions { hidden });
+        }
+
+        self.db
+            .add_lifeline(user_id, key, lifeline)
+            .await
+            .or(Err(DatabaseError))?;
+
+        Ok(replies)
+    }
+}
+
+/// Timed messages are stamped with their send time on the way out.
+fn game_start(live: &LiveGame) -> ServerMessage {
+    ServerMessage::GameStart {
+        sent_at: 0,
+        deadline: deadline_millis(live.question_opens(0)),
+    }
+}
+
+fn question_message(question: QuestionView, closes: Instant) -> ServerMessage {
+    ServerMessage::Question {
+        question,
+        sent_at: 0,
+        deadline: deadline_millis(closes),
+    }
+}
+
+fn pong(client_time: u64) -> ServerMessage {
+    ServerMessage::Pong {
+        client_time,
+        received_at: now_millis(),
+        sent_at: 0,
+    }
+}
+
+fn reveal(
+    question: &Question,
+    options: &OptionOrder,
+    status: AnswerStatus,
+    answer: Option<Answer>,
+    reveal_ends: Instant,
+) -> ServerMessage {
+    let deadline = deadline_millis(reveal_ends);
+    match &question.kind {
+        QuestionKind::Choice { answer_idx, .. } => ServerMessage::Answer {
+            status,
+            answer_idx: options.to_shown(answer_idx),
+            sent_at: 0,
+            deadline,
+        },
+        QuestionKind::Text {
+            accepted_answers, ..
+        } => ServerMessage::TextAnswer {
+            status,
+            answer: accepted_answers[0].clone(),
+            sent_at: 0,
+            deadline,
+        },
+        QuestionKind::Number {
+            answer: true_value, ..
+        } => ServerMessage::NumberAnswer {
+            status,
+            answer: *true_value,
+            error: match answer {
+                Some(Answer::Number(estimate)) => Some((estimate - true_value).abs()),
+                _ => None,
+            },
+            sent_at: 0,
+            deadline,
+        },
+    }
+}
+
+/// Malformed frames a connection has left before it is dropped.
+struct ErrorBudget {
+    remaining: u32,
+}
+
+impl Default for ErrorBudget {
+    fn default() -> Self {
+        Self {
+            remaining: ERROR_BUDGET,
+        }
+    }
+}
+
+impl ErrorBudget {
+    /// Spends one error on a malformed frame. Returns the reply for the
+    /// client and whether the connection may stay open.
+    fn spend(&mut self, e: CodecError) -> (ServerMessage, bool) {
+        if self.remaining == 0 {
+            let message = format!("more than {ERROR_BUDGET} malformed messages");
+            return (
+                ServerMessage::error(ErrorCode::TooManyErrors, message),
+                false,
+            );
+        }
+
+        self.remaining -= 1;
+        (
+            ServerMessage::error(ErrorCode::MalformedMessage, e.to_string()),
+            true,
+        )
+    }
+}
+
+/// Replies to a `Hello`. Returns the encoding the client reads from now on,
+/// or `None` if it cannot stay connected.
+fn greet(
+    tx: &UnboundedSender<ServerMessage>,
+    protocol_version: u32,
+    client: &str,
+    encodings: &[String],
+) -> Option<Encoding> {
+    let (reply, negotiated) = match welcome(protocol_version, client, encodings) {
+        Ok(reply) => match reply {
+            ServerMessage::Welcome { encoding, .. } => (reply, Some(encoding)),
+            _ => (reply, None),
+        },
+        Err(e) => (
+            ServerMessage::error(ErrorCode::UnsupportedProtocol, e.to_string()),
+            None,
+        ),
+    };
+
+    if let Err(e) = tx.send(reply) {
+        log::error!("Failed to send welcome to {client}: {e}");
+    }
+    negotiated
+}
+
+/// Waits briefly for the client's `Hello`, which has to come before anything
+/// else is sent. Clients that say nothing in time, or start with another
+/// message, speak the first protocol version in JSON, and that message is
+/// handed back to be handled like any other.
+async fn handshake<S>(
+    incoming: &mut S,
+    tx: &UnboundedSender<ServerMessage>,
+    idle_timeout: Duration,
+) -> Result<(Encoding, Option<Message>), Disconnect>
+where
+    S: Stream<Item = Result<Message, warp::Error>> + Unpin,
+{
+    let msg = match timeout(HELLO_TIMEOUT, receive(incoming, idle_timeout)).await {
+        Ok(msg) => msg?,
+        Err(_) => return Ok((Encoding::default(), None)),
+    };
+
+    match decode_message(&msg, Encoding::default()) {
+        Ok(ClientMessage::Hello {
+            protocol_version,
+            client,
+            encodings,
+        }) => match greet(tx, protocol_version, &client, &encodings) {
+            Some(encoding) => Ok((encoding, None)),
+            None => Err(Disconnect::UnsupportedProtocol),
+        },
+        _ => Ok((Encoding::default(), Some(msg))),
+    }
+}
+
+/// Runs the handshake on a socket that has not joined a game yet and answers
+/// its `Hello` straight away. Returns `None` once the socket is closed, after
+/// telling the client why if it is owed that.
+pub async fn greet_socket<Socket>(ws: &mut Socket) -> Option<Handshake>
+where
+    Socket: Stream<Item = Result<Message, warp::Error>> + Sink<Message> + Unpin,
+{
+    let (tx, mut rx) = unbounded_channel::<ServerMessage>();
+    let handshake = handshake(ws, &tx, IDLE_TIMEOUT).await;
+    drop(tx);
+
+    // Nothing was negotiated before the reply, so it goes out in JSON.
+    while let Some(mut msg) = rx.recv().await {
+        msg.stamp(now_millis());
+        if ws
+            .send(encode_or_error(&msg, Encoding::default()))
+            .await
+            .is_err()
+        {
+            return None;
+        }
+    }
+
+    match handshake {
+        Ok((encoding, first_frame)) => Some(Handshake {
+            encoding,
+            first_frame,
+        }),
+        Err(reason) => {
+            log::info!("Connection closed before joining: {reason}");
+            let _ = timeout(FLUSH_TIMEOUT, ws.close()).await;
+            None
+        }
+    }
+}
+
+fn unexpected_hello() -> ServerMessage {
+    ServerMessage::error(
+        ErrorCode::UnexpectedHello,
+        "Hello is only accepted as the first message",
+    )
+}
+
+/// Sends what is left for a connection that closes before its session
+/// started, if it is owed an explanation.
+async fn farewell<S>(
+    rx: UnboundedReceiverStream<ServerMessage>,
+    outgoing: S,
+    heartbeat: Duration,
+    reason: &Disconnect,
+) where
+    S: Sink<Message>,
+{
+    if !reason.has_farewell() {
+        return;
+    }
+
+    let flush = outgoing_messages(rx, heartbeat, Encoding::default())
+        .map(Ok)
+        .forward(outgoing);
+    if timeout(FLUSH_TIMEOUT, flush).await.is_err() {
+        log::error!("Failed to close connection");
+    }
+}
+
+/// Waits for the next frame that carries data. Any frame, pongs included,
+/// shows that the client is still there.
+async fn receive<S>(incoming: &mut S, idle_timeout: Duration) -> Result<Message, Disconnect>
+where
+    S: Stream<Item = Result<Message, warp::Error>> + Unpin,
+{
+    loop {
+        let msg = match timeout(idle_timeout, incoming.next()).await {
+            Ok(Some(Ok(msg))) => msg,
+            Ok(Some(Err(e))) => return Err(Disconnect::ReceiveFailed(e)),
+            Ok(None) => return Err(Disconnect::ClientClosed),
+            Err(_) => return Err(Disconnect::IdleTimeout(idle_timeout)),
+        };
+
+        if msg.is_close() {
+            // Reading on until the stream ends lets the socket answer the
+            // close frame before it is dropped.
+            let drain = async { while incoming.next().await.is_some() {} };
+            let _ = timeout(FLUSH_TIMEOUT, drain).await;
+            return Err(Disconnect::ClientClosed);
+        }
+
+        if !msg.is_ping() && !msg.is_pong() {
+            return Ok(msg);
+        }
+    }
+}
+
+/// Encodes the messages for the socket with a ping every `heartbeat`, and
+/// closes the socket once the channel closes. `encoding` is what the client
+/// reads until a welcome announces another one.
+fn outgoing_messages(
+    rx: UnboundedReceiverStream<ServerMessage>,
+    heartbeat: Duration,
+    encoding: Encoding,
+) -> impl Stream<Item = Message> {
+    // The welcome still goes out in JSON, everything after it in the
+    // encoding it announces.
+    let messages = rx
+        .scan(encoding, |encoding, mut msg| {
+            msg.stamp(now_millis());
+            let frame = encode_or_error(&msg, *encoding);
+            if let ServerMessage::Welcome {
+                encoding: negotiated,
+                ..
+            } = msg
+            {
+                *encoding = negotiated;
+            }
+            future::ready(Some(Some(frame)))
+        })
+        .chain(stream::once(async { None }));
+    let pings = stream::unfold((), move |()| async move {
+        sleep(heartbeat).await;
+        Some((Some(Message::ping(Vec::new())), ()))
+    });
+
+    stream::select(messages, pings)
+        .take_while(|msg| future::ready(msg.is_some()))
+        .filter_map(future::ready)
+        .chain(stream::once(async { Message::close() }))
+}
+
+/// Sends the message to every player and spectator, forgetting spectators
+/// who have left.
+async fn broadcast(
+    connections: &Mutex<HashMap<String, Connection>>,
+    spectators: &Mutex<Vec<UnboundedSender<ServerMessage>>>,
+    msg: ServerMessage,
+) {
+    for (user_id, connection) in connections.lock().await.iter() {
+        if let Err(e) = connection.tx.send(msg.clone()) {
+            log::error!("Failed to send message to {user_id}: {e}");
+        }
+    }
+
+    spectators
+        .lock()
+        .await
+        .retain(|spectator| spectator.send(msg.clone()).is_ok());
+}
+
+fn preload(tx: &UnboundedSender<ServerMessage>, question: &Question, user_id: &str) {
+    let media = question.all_media();
+    if media.is_empty() {
+        return;
+    }
+
+    if let Err(e) = tx.send(ServerMessage::Preload { media }) {
+        log::error!("Failed to send preload message to {user_id}: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorBudget, GameController, ERROR_BUDGET};
+    use crate::{
+        adapters::{GameMemoryDatabase, Notifier},
+        controllers::{decode_message, CodecError, ManualStart, PROTOCOL_VERSION},
+        models::{
+            Answer, AnswerStatus, ClientMessage, Encoding, ErrorCode, Lifeline, OptionIndex,
+            ServerMessage,
+        },
+    };
+    use futures_util::{Sink, Stream, StreamExt};
+    use std::{
+        convert::Infallible,
+        pin::Pin,
+        task::{Context, Poll},
+        time::Duration,
+    };
+    use tokio::{
+        sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        task::JoinHandle,
+        time::Instant,
+    };
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use warp::ws::Message;
+
+    type TestController = GameController<GameMemoryDatabase, ManualStart, Notifier>;
+
+    /// The server's end of a websocket whose client is driven by the test.
+    struct TestSocket {
+        incoming: UnboundedReceiverStream<Message>,
+        outgoing: UnboundedSender<Message>,
+    }
+
+    impl Stream for TestSocket {
+        type Item = Result<Message, warp::Error>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.incoming.poll_next_unpin(cx).map(|msg| msg.map(Ok))
+        }
+    }
+
+    impl Sink<Message> for TestSocket {
+        type Error = Infallible;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(self: Pin<&mut Self>, msg: Message) -> Result<(), Self::Error> {
+            // the client may have hung up already
+            let _ = self.outgoing.send(msg);
+            Ok(())
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// The client's end, which speaks JSON. Dropping it closes the socket.
+    struct TestClient {
+        outgoing: UnboundedSender<Message>,
+        incoming: UnboundedReceiver<Message>,
+    }
+
+    impl TestClient {
+        fn send(&self, msg: &ClientMessage) {
+            let text = serde_json::to_string(msg).unwrap();
+            self.outgoing.send(Message::text(text)).unwrap();
+        }
+
+        /// The next message from the server, skipping pings. `None` once the
+        /// server closes the socket.
+        async fn recv(&mut self) -> Option<ServerMessage> {
+            loop {
+                let msg = self.incoming.recv().await?;
+                if msg.is_close() {
+                    return None;
+                }
+                if let Ok(text) = msg.to_str() {
+                    return Some(serde_json::from_str(text).unwrap());
+                }
+            }
+        }
+
+        /// Says hello so the session starts without waiting for it, and
+        /// returns the welcome.
+        async fn hello(&mut self) -> ServerMessage {
+            self.send(&ClientMessage::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                client: "test".into(),
+                encodings: Vec::new(),
+            });
+            self.recv().await.unwrap()
+        }
+    }
+
+    fn connect() -> (TestSocket, TestClient) {
+        let (client_tx, server_rx) = unbounded_channel();
+        let (server_tx, client_rx) = unbounded_channel();
+        let socket = TestSocket {
+            incoming: UnboundedReceiverStream::new(server_rx),
+            outgoing: server_tx,
+        };
+        let client = TestClient {
+            outgoing: client_tx,
+            incoming: client_rx,
+        };
+        (socket, client)
+    }
+
+    /// Starts a session of the player over a fresh socket and says hello.
+    async fn join(controller: &TestController, user_id: &str) -> (JoinHandle<()>, TestClient) {
+        let (socket, mut client) = connect();
+        let session = tokio::spawn(controller.clone().start(user_id.into(), socket));
+
+        assert!(matches!(
+            client.hello().await,
+            ServerMessage::Welcome { .. }
+        ));
+        (session, client)
+    }
+
+    fn get_controller() -> TestController {
+        GameController::new(
+            GameMemoryDatabase::default(),
+            ManualStart,
+            Notifier::default(),
+        )
+    }
+
+    fn malformed() -> CodecError {
+        CodecError::Parse("expected value".into())
+    }
+
+    #[test]
+    fn disconnects_once_the_error_budget_is_spent() {
+        let mut budget = ErrorBudget::default();
+
+        for _ in 0..ERROR_BUDGET {
+            let (reply, within_budget) = budget.spend(malformed());
+            assert!(within_budget);
+            assert!(matches!(
+                reply,
+                ServerMessage::Error {
+                    code: ErrorCode::MalformedMessage,
+                    ..
+                }
+            ));
+        }
+
+        let (reply, within_budget) = budget.spend(malformed());
+        assert!(!within_budget);
+        assert!(matches!(
+            reply,
+            ServerMessage::Error {
+                code: ErrorCode::TooManyErrors,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn binary_frames_on_json_connections_are_malformed() {
+        let frame = Message::binary(vec![0x81, 0xa4]);
+        let e = decode_message(&frame, Encoding::Json).unwrap_err();
+        assert!(matches!(e, CodecError::UnexpectedFrame("text")));
+
+        let (reply, within_budget) = ErrorBudget::default().spend(e);
+        assert!(within_budget);
+        assert!(matches!(
+            reply,
+            ServerMessage::Error {
+                code: ErrorCode::MalformedMessage,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn forgets_spectators_once_their_socket_closes() {
+        let controller = get_controller();
+        let (socket, mut client) = connect();
+        let watching = tokio::spawn(controller.clone().spectate(socket));
+
+        assert!(matches!(
+            client.hello().await,
+            ServerMessage::Welcome { .. }
+        ));
+        assert!(matches!(
+            client.recv().await,
+            Some(ServerMessage::WaitingForHost)
+        ));
+        assert_eq!(controller.spectators.lock().await.len(), 1);
+
+        drop(client);
+        watching.await.unwrap();
+        assert!(controller.spectators.lock().await.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resumes_the_question_after_reconnecting() {
+        let controller = get_controller();
+        controller.begin_game().await.unwrap();
+
+        let (session, mut client) = join(&controller, "player").await;
+        assert!(matches!(
+            client.recv().await,
+            Some(ServerMessage::Resume {
+                question_index: 0,
+                question: None,
+                ..
+            })
+        ));
+        assert!(matches!(
+            client.recv().await,
+            Some(ServerMessage::Question { .. })
+        ));
+        client.send(&ClientMessage::Answer {
+            answer_idx: OptionIndex::One,
+        });
+        drop(client);
+        session.await.unwrap();
+
+        let (_session, mut client) = join(&controller, "player").await;
+        match client.recv().await {
+            Some(ServerMessage::Resume {
+                question_index,
+                question,
+                answer,
+                revealing,
+                ..
+            }) => {
+                assert_eq!(question_index, 0);
+                assert!(question.is_some());
+                assert_eq!(answer, Some(Answer::Choice(OptionIndex::One)));
+                assert!(!revealing);
+            }
+            msg => panic!("expected a resume, got {msg:?}"),
+        }
+        assert!(matches!(
+            client.recv().await,
+            Some(ServerMessage::Answer {
+                status: AnswerStatus::Correct,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn locks_the_answer_unless_a_second_chance_is_used() {
+        let controller = get_controller();
+        controller.begin_game().await.unwrap();
+
+        let (_session, mut client) = join(&controller, "player").await;
+        assert!(matches!(
+            client.recv().await,
+            Some(ServerMessage::Resume { .. })
+        ));
+        assert!(matches!(
+            client.recv().await,
+            Some(ServerMessage::Question { .. })
+        ));
+        let answer = |answer_idx| ClientMessage::Answer { answer_idx };
+        let locked = |msg| {
+            matches!(
+                msg,
+                Some(ServerMessage::Error {
+                    code: ErrorCode::AnswerLocked,
+                    ..
+                })
+            )
+        };
+
+        client.send(&answer(OptionIndex::Two));
+        client.send(&answer(OptionIndex::One));
+        assert!(locked(client.recv().await));
+
+        client.send(&ClientMessage::UseLifeline {
+            lifeline: Lifeline::SecondChance,
+        });
+        assert!(matches!(
+            client.recv().await,
+            Some(ServerMessage::LifelineUsed {
+                lifeline: Lifeline::SecondChance,
+                ..
+            })
+        ));
+        client.send(&answer(OptionIndex::One));
+        client.send(&answer(OptionIndex::Three));
+        assert!(locked(client.recv().await));
+
+        assert!(matches!(
+            client.recv().await,
+            Some(ServerMessage::Answer {
+                status: AnswerStatus::Correct,
+                answer_idx: OptionIndex::One,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_new_connection_takes_over_from_the_old_one() {
+        let controller = get_controller();
+
+        let (first_session, mut first) = join(&controller, "player").await;
+        assert!(matches!(
+            first.recv().await,
+            Some(ServerMessage::WaitingForHost)
+        ));
+        let (_second_session, mut second) = join(&controller, "player").await;
+        assert!(matches!(
+            second.recv().await,
+            Some(ServerMessage::WaitingForHost)
+        ));
+
+        assert!(matches!(
+            first.recv().await,
+            Some(ServerMessage::Error {
+                code: ErrorCode::SessionReplaced,
+                ..
+            })
+        ));
+        assert!(first.recv().await.is_none());
+        first_session.await.unwrap();
+        assert_eq!(controller.players(), 1);
+        assert!(controller.connections.lock().await.contains_key("player"));
+
+        second.send(&ClientMessage::Ping { client_time: 1 });
+        assert!(matches!(
+            second.recv().await,
+            Some(ServerMessage::Pong { client_time: 1, .. })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drops_connections_that_stop_answering_pings() {
+        let idle_timeout = Duration::from_secs(3);
+        let controller = get_controller().with_heartbeat(Duration::from_secs(1), idle_timeout);
+
+        let (session, mut client) = join(&controller, "player").await;
+        assert!(matches!(
+            client.recv().await,
+            Some(ServerMessage::WaitingForHost)
+        ));
+
+        // pongs count as frames, so a quiet player who answers pings stays
+        for _ in 0..5 {
+            let frame = client.incoming.recv().await.unwrap();
+            assert!(frame.is_ping());
+            client.outgoing.send(Message::pong(Vec::new())).unwrap();
+        }
+        assert!(!session.is_finished());
+        assert_eq!(controller.players(), 1);
+
+        let quiet_since = Instant::now();
+        assert!(client.recv().await.is_none());
+        assert!(quiet_since.elapsed() >= idle_timeout);
+        session.await.unwrap();
+        assert_eq!(controller.players(), 0);
+        assert!(controller.connections.lock().await.is_empty());
     }
 }
 
@@ -0,0 +1,282 @@
+use crate::models::{Answer, AnswerStatus, Question, QuestionKind, MAX_POINTS};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+pub fn grade(question: &Question, answer: Option<&Answer>) -> AnswerStatus {
+    grade_among(question, answer, &[])
+}
+
+/// Grades an answer against every answer given to the same question. Only
+/// estimates depend on the others: the closer ones earn more.
+pub fn grade_among(
+    question: &Question,
+    answer: Option<&Answer>,
+    answers: &[Answer],
+) -> AnswerStatus {
+    let answer = match answer {
+        Some(answer) => answer,
+        None => return AnswerStatus::NoAnswer,
+    };
+
+    if let (QuestionKind::Number { answer, tolerance }, Answer::Number(estimate)) =
+        (&question.kind, answer)
+    {
+        return grade_estimate(*answer, *tolerance, *estimate, answers);
+    }
+
+    let correct = match (&question.kind, answer) {
+        (QuestionKind::Choice { answer_idx, .. }, Answer::Choice(answer)) => answer == answer_idx,
+        (
+            QuestionKind::Text {
+                accepted_answers,
+                max_distance,
+            },
+            Answer::Text(answer),
+        ) => text_matches(accepted_answers, answer, *max_distance),
+        _ => false,
+    };
+
+    if correct {
+        AnswerStatus::Correct
+    } else {
+        AnswerStatus::Incorrect
+    }
+}
+
+/// Counts how many players picked each option and how many were correct.
+pub fn answer_distribution(question: &Question, answers: &[Answer]) -> (Vec<u32>, u32) {
+    let mut counts = vec![0; question.options().len()];
+
+    for answer in answers {
+        if let Answer::Choice(idx) = answer {
+            if let Some(count) = counts.get_mut(idx.index()) {
+                *count += 1;
+            }
+        }
+    }
+
+    let correct = answers
+        .iter()
+        .filter(|answer| grade_among(question, Some(answer), answers) == AnswerStatus::Correct)
+        .count() as u32;
+
+    (counts, correct)
+}
+
+/// Awards points on a sliding scale. An exact estimate is correct, one off by
+/// `tolerance` or more is incorrect, and the ones in between are close: their
+/// points fall off with the error, and by one more for each estimate that
+/// was closer, down to a single point.
+fn grade_estimate(
+    answer: f64,
+    tolerance: f64,
+    estimate: f64,
+    estimates: &[Answer],
+) -> AnswerStatus {
+    let error = (estimate - answer).abs();
+    if error >= tolerance {
+        return AnswerStatus::Incorrect;
+    }
+    if error == 0.0 {
+        return AnswerStatus::Correct;
+    }
+
+    let closer = estimates
+        .iter()
+        .filter(|other| matches!(other, Answer::Number(other) if (other - answer).abs() < error))
+        .count() as u32;
+    let points = (MAX_POINTS as f64 * (tolerance - error) / tolerance).floor() as u32;
+
+    AnswerStatus::Close {
+        rank: closer + 1,
+        points: points.min(MAX_POINTS - 1).saturating_sub(closer).max(1),
+    }
+}
+
+fn text_matches(accepted_answers: &[String], answer: &str, max_distance: usize) -> bool {
+    let answer = normalize(answer);
+    if answer.is_empty() {
+        return false;
+    }
+
+    accepted_answers
+        .iter()
+        .map(|accepted| normalize(accepted))
+        .filter(|accepted| !accepted.is_empty())
+        .any(|accepted| {
+            edit_distance(&accepted, &answer) <= allowed_distance(&accepted, max_distance)
+        })
+}
+
+/// Shrinks the typo allowance for short answers, where a couple of edits turn
+/// "UK" into "US", and drops it for answers with digits, where "Apollo 11" and
+/// "Apollo 12" are different answers.
+fn allowed_distance(accepted: &str, max_distance: usize) -> usize {
+    if accepted.chars().any(|c| c.is_ascii_digit()) {
+        return 0;
+    }
+
+    match accepted.chars().count() {
+        0..=3 => 0,
+        4..=7 => max_distance.min(1),
+        _ => max_distance,
+    }
+}
+
+/// Lowercases, strips accents and collapses whitespace so that
+/// "  Addis   Abäba " and "addis ababa" compare equal.
+pub fn normalize(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{answer_distribution, edit_distance, grade, grade_among, normalize};
+    use crate::models::{Answer, AnswerStatus, OptionIndex, Question};
+
+    #[test]
+    fn normalizes_case_accents_and_whitespace() {
+        assert_eq!(normalize("  Addis   Abäba "), "addis ababa");
+        assert_eq!(normalize("São\tPaulo"), "sao paulo");
+    }
+
+    #[test]
+    fn computes_edit_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", "abc"), 0);
+    }
+
+    #[test]
+    fn grades_text_answers_tolerantly() {
+        let question = Question::text(
+            "What is the capital of Ethiopia?".into(),
+            vec!["Addis Ababa".into()],
+        );
+        let grade_text = |answer: &str| grade(&question, Some(&Answer::Text(answer.into())));
+
+        assert_eq!(grade_text("addis abeba"), AnswerStatus::Correct);
+        assert_eq!(grade_text(" ADDIS  ÁBABA"), AnswerStatus::Correct);
+        assert_eq!(grade_text("Nairobi"), AnswerStatus::Incorrect);
+        assert_eq!(grade_text(""), AnswerStatus::Incorrect);
+        assert_eq!(grade(&question, None), AnswerStatus::NoAnswer);
+    }
+
+    #[test]
+    fn matches_short_and_numeric_answers_exactly() {
+        let grade_text = |accepted: &str, answer: &str| {
+            let question = Question::text("Which one?".into(), vec![accepted.into()]);
+            grade(&question, Some(&Answer::Text(answer.into())))
+        };
+
+        assert_eq!(grade_text("UK", "US"), AnswerStatus::Incorrect);
+        assert_eq!(grade_text("UK", "uk"), AnswerStatus::Correct);
+        assert_eq!(grade_text("Paris", "Pariss"), AnswerStatus::Correct);
+        assert_eq!(grade_text("Paris", "Parris!"), AnswerStatus::Incorrect);
+        assert_eq!(grade_text("1984", "1985"), AnswerStatus::Incorrect);
+        assert_eq!(
+            grade_text("Apollo 11", "Apollo 12"),
+            AnswerStatus::Incorrect
+        );
+    }
+
+    #[test]
+    fn ranks_estimates_among_players() {
+        let question = Question::number("How many regions are there?".into(), 100.0, 40.0);
+        let answers = [91.0, 103.0, 118.0, 103.0, 160.0].map(Answer::Number);
+        let grade_number =
+            |answer: f64| grade_among(&question, Some(&Answer::Number(answer)), &answers);
+
+        assert_eq!(grade_number(100.0), AnswerStatus::Correct);
+        assert_eq!(
+            grade_number(103.0),
+            AnswerStatus::Close { rank: 1, points: 9 }
+        );
+        assert_eq!(
+            grade_number(91.0),
+            AnswerStatus::Close { rank: 3, points: 5 }
+        );
+        assert_eq!(
+            grade_number(118.0),
+            AnswerStatus::Close { rank: 4, points: 2 }
+        );
+        assert_eq!(grade_number(160.0), AnswerStatus::Incorrect);
+
+        // alone, an estimate is only paid for its error
+        assert_eq!(
+            grade(&question, Some(&Answer::Number(130.0))),
+            AnswerStatus::Close { rank: 1, points: 2 }
+        );
+        assert_eq!(
+            grade(&question, Some(&Answer::Number(140.0))),
+            AnswerStatus::Incorrect
+        );
+    }
+
+    #[test]
+    fn pays_less_for_each_place() {
+        let question = Question::number("How tall is the tower in metres?".into(), 100.0, 40.0);
+        let answers = [102.0, 95.0, 110.0, 170.0].map(Answer::Number);
+        let points: Vec<u32> = answers
+            .iter()
+            .map(|answer| grade_among(&question, Some(answer), &answers).points())
+            .collect();
+
+        assert_eq!(points, vec![9, 7, 5, 0]);
+        assert!(points.windows(2).all(|pair| pair[0] > pair[1]));
+    }
+
+    #[test]
+    fn counts_answers_per_option() {
+        let question = Question::choice(
+            "What is the question?".into(),
+            vec!["A".into(), "B".into(), "C".into()],
+            OptionIndex::Two,
+        );
+        let answers = [
+            Answer::Choice(OptionIndex::Two),
+            Answer::Choice(OptionIndex::Two),
+            Answer::Choice(OptionIndex::Three),
+            Answer::Text("B".into()),
+        ];
+
+        assert_eq!(answer_distribution(&question, &answers), (vec![0, 2, 1], 2));
+    }
+
+    #[test]
+    fn grades_mismatched_answer_kinds_as_incorrect() {
+        let question = Question::true_false("Is the sky blue?".into(), true);
+
+        assert_eq!(
+            grade(&question, Some(&Answer::Choice(OptionIndex::One))),
+            AnswerStatus::Correct
+        );
+        assert_eq!(
+            grade(&question, Some(&Answer::Text("True".into()))),
+            AnswerStatus::Incorrect
+        );
+    }
+}
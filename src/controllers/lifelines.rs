@@ -0,0 +1,136 @@
+use crate::models::{Lifeline, OptionIndex, Question, QuestionKind, LIFELINES_PER_GAME};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum LifelineError {
+    #[error("failed to get lifelines from the database")]
+    DatabaseError,
+    #[error("there is no open question")]
+    NoQuestion,
+    #[error("no lifelines of this kind are left")]
+    NoneLeft,
+    #[error("this lifeline was already used on this question")]
+    AlreadyUsed,
+    #[error("this lifeline can't be used on this question")]
+    NotApplicable,
+}
+
+/// Lifelines of a kind left in the game whose question keys start with
+/// `game_prefix`. Lifelines of earlier games do not count, even when they
+/// were never cleared because the player left before the end.
+pub fn remaining_lifelines(
+    used: &[(String, Lifeline)],
+    game_prefix: &str,
+    lifeline: Lifeline,
+) -> u32 {
+    let used = used
+        .iter()
+        .filter(|(q, l)| q.starts_with(game_prefix) && *l == lifeline)
+        .count() as u32;
+    LIFELINES_PER_GAME.saturating_sub(used)
+}
+
+pub fn used_on(used: &[(String, Lifeline)], question: &str, lifeline: Lifeline) -> bool {
+    used.iter().any(|(q, l)| q == question && *l == lifeline)
+}
+
+/// Picks up to two wrong options to hide, always leaving the answer and at
+/// least one wrong option visible.
+pub fn fifty_fifty<R: Rng>(question: &Question, rng: &mut R) -> Option<Vec<OptionIndex>> {
+    let (options, answer_idx) = match &question.kind {
+        QuestionKind::Choice {
+            options,
+            answer_idx,
+            ..
+        } => (options, answer_idx),
+        _ => return None,
+    };
+
+    let mut wrong: Vec<OptionIndex> = (0..options.len())
+        .filter_map(OptionIndex::from_index)
+        .filter(|idx| idx != answer_idx)
+        .collect();
+
+    let hide = wrong.len().saturating_sub(1).min(2);
+    if hide == 0 {
+        return None;
+    }
+
+    wrong.shuffle(rng);
+    wrong.truncate(hide);
+    wrong.sort_by_key(OptionIndex::index);
+    Some(wrong)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fifty_fifty, remaining_lifelines};
+    use crate::{
+        controllers::LiveGame,
+        models::{Game, Lifeline, OptionIndex, Question},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+    use tokio::time::Instant;
+
+    fn sample_question(options: usize) -> Question {
+        Question::choice(
+            "What is the question?".into(),
+            (1..=options).map(|i| format!("Option {i}")).collect(),
+            OptionIndex::Two,
+        )
+    }
+
+    #[test]
+    fn fifty_fifty_hides_two_wrong_options() {
+        let mut rng = StdRng::seed_from_u64(7);
+
+        for _ in 0..20 {
+            let hidden = fifty_fifty(&sample_question(4), &mut rng).unwrap();
+            assert_eq!(hidden.len(), 2);
+            assert!(!hidden.contains(&OptionIndex::Two));
+        }
+
+        assert_eq!(
+            fifty_fifty(&sample_question(3), &mut rng).map(|h| h.len()),
+            Some(1)
+        );
+        assert_eq!(fifty_fifty(&sample_question(2), &mut rng), None);
+        assert_eq!(
+            fifty_fifty(&Question::text("Who?".into(), vec!["Me".into()]), &mut rng),
+            None
+        );
+    }
+
+    #[test]
+    fn counts_remaining_lifelines() {
+        let used = vec![("game:Question 1".to_string(), Lifeline::Skip)];
+
+        assert_eq!(remaining_lifelines(&used, "game:", Lifeline::Skip), 0);
+        assert_eq!(remaining_lifelines(&used, "game:", Lifeline::FiftyFifty), 1);
+    }
+
+    #[test]
+    fn lifelines_left_over_from_an_earlier_game_do_not_count() {
+        let game = Game {
+            questions: vec![sample_question(4)],
+        };
+        let (first, next) = (
+            LiveGame::new(game.clone(), Instant::now()),
+            LiveGame::new(game.clone(), Instant::now()),
+        );
+
+        // the player left the first game before its end, so nothing was cleared
+        let used = vec![(first.question_key(0), Lifeline::Skip)];
+
+        assert_eq!(
+            remaining_lifelines(&used, &first.key_prefix(), Lifeline::Skip),
+            0
+        );
+        assert_eq!(
+            remaining_lifelines(&used, &next.key_prefix(), Lifeline::Skip),
+            1
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use crate::models::Game;
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub const LOBBY_DURATION: Duration = Duration::from_secs(10);
+pub const QUESTION_DURATION: Duration = Duration::from_secs(10);
+pub const REVEAL_DURATION: Duration = Duration::from_secs(10);
+
+/// A game that has started, shared by every connection so that players who
+/// join late can work out where in the game they are.
+#[derive(Debug, Clone)]
+pub struct LiveGame {
+    pub id: String,
+    pub game: Game,
+    pub started_at: Instant,
+    pub lobby: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Lobby,
+    Question(usize),
+    Reveal(usize),
+    Ended,
+}
+
+impl LiveGame {
+    pub fn new(game: Game, started_at: Instant) -> Self {
+        Self {
+            id: format!("{:016x}", rand::random::<u64>()),
+            game,
+            started_at,
+            lobby: LOBBY_DURATION,
+        }
+    }
+
+    pub fn with_lobby(mut self, lobby: Duration) -> Self {
+        self.lobby = lobby;
+        self
+    }
+
+    /// Key under which the answers, results and lifelines of the question at
+    /// `idx` are stored, so that a question asked again in a later game
+    /// starts afresh. Questions are told apart by their place in the game, as
+    /// two of them may read the same.
+    pub fn question_key(&self, idx: usize) -> String {
+        format!("{}{idx}", self.key_prefix())
+    }
+
+    /// Start of the keys of every question of this game.
+    pub fn key_prefix(&self) -> String {
+        format!("{}:", self.id)
+    }
+
+    pub fn question_opens(&self, idx: usize) -> Instant {
+        self.started_at + self.lobby + (QUESTION_DURATION + REVEAL_DURATION) * idx as u32
+    }
+
+    pub fn question_closes(&self, idx: usize) -> Instant {
+        self.question_opens(idx) + QUESTION_DURATION
+    }
+
+    pub fn ends_at(&self) -> Instant {
+        self.question_opens(self.game.questions.len())
+    }
+
+    pub fn phase(&self, now: Instant) -> Phase {
+        if now < self.started_at + self.lobby {
+            return Phase::Lobby;
+        }
+
+        (0..self.game.questions.len())
+            .find_map(|idx| {
+                if now < self.question_closes(idx) {
+                    Some(Phase::Question(idx))
+                } else if now < self.question_opens(idx + 1) {
+                    Some(Phase::Reveal(idx))
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(Phase::Ended)
+    }
+
+    pub fn phase_ends(&self, phase: Phase) -> Instant {
+        match phase {
+            Phase::Lobby => self.question_opens(0),
+            Phase::Question(idx) => self.question_closes(idx),
+            Phase::Reveal(idx) => self.question_opens(idx + 1),
+            Phase::Ended => self.ends_at(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LiveGame, Phase};
+    use crate::models::{Game, Question};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    fn sample_game() -> LiveGame {
+        let question = Question::true_false("Is the sky blue?".into(), true);
+        let game = Game {
+            questions: vec![question.clone(), question],
+        };
+
+        LiveGame::new(game, Instant::now())
+    }
+
+    #[test]
+    fn follows_the_game_timeline() {
+        let live = sample_game();
+        let at = |secs| live.phase(live.started_at + Duration::from_secs(secs));
+
+        assert_eq!(at(0), Phase::Lobby);
+        assert_eq!(at(10), Phase::Question(0));
+        assert_eq!(at(25), Phase::Reveal(0));
+        assert_eq!(at(30), Phase::Question(1));
+        assert_eq!(at(49), Phase::Reveal(1));
+        assert_eq!(at(50), Phase::Ended);
+    }
+
+    #[test]
+    fn knows_when_each_phase_ends() {
+        let live = sample_game();
+        let ends = |phase| live.phase_ends(phase) - live.started_at;
+
+        assert_eq!(ends(Phase::Lobby), Duration::from_secs(10));
+        assert_eq!(ends(Phase::Question(1)), Duration::from_secs(40));
+        assert_eq!(ends(Phase::Reveal(1)), Duration::from_secs(50));
+    }
+
+    #[test]
+    fn lobby_can_be_shortened() {
+        let live = sample_game().with_lobby(Duration::from_secs(2));
+
+        assert_eq!(
+            live.question_opens(0) - live.started_at,
+            Duration::from_secs(2)
+        );
+        assert_eq!(live.ends_at() - live.started_at, Duration::from_secs(42));
+    }
+
+    #[test]
+    fn keys_questions_by_game() {
+        let (first, second) = (sample_game(), sample_game());
+
+        assert_ne!(first.question_key(0), second.question_key(0));
+        // both sample questions read the same
+        assert_ne!(first.question_key(0), first.question_key(1));
+    }
+}
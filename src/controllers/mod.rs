@@ -1,4 +1,36 @@
+mod chat;
+mod clock;
+mod composer;
+mod duels;
 mod game;
+mod grading;
+mod lifelines;
+mod live_game;
+mod practice;
+mod protocol;
+mod question_bank;
+mod question_pack;
+mod reactions;
+mod rooms;
+mod shuffle;
+mod sse;
+mod teams;
 mod users;
+pub use chat::*;
+pub use clock::*;
+pub use composer::*;
+pub use duels::*;
 pub use game::*;
+pub use grading::*;
+pub use lifelines::*;
+pub use live_game::*;
+pub use practice::*;
+pub use protocol::*;
+pub use question_bank::*;
+pub use question_pack::*;
+pub use reactions::*;
+pub use rooms::*;
+pub use shuffle::*;
+pub use sse::*;
+pub use teams::*;
 pub use users::*;
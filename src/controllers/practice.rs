@@ -0,0 +1,281 @@
+use crate::{
+    controllers::{
+        generate_join_code, GameController, ManualStart, RoomDatabase, RoomGameController,
+        CHECK_INTERVAL, CONNECT_TIMEOUT,
+    },
+    models::{Game, ServerMessage},
+    ports::{GameDatabase, GameStartNotifier, QuestionStore},
+};
+use std::{marker::PhantomData, time::Duration};
+use thiserror::Error;
+use tokio::time::{sleep, Instant};
+
+pub const PRACTICE_QUESTIONS: usize = 5;
+const PRACTICE_LOBBY: Duration = Duration::from_secs(3);
+
+pub type PracticeGameController<GD, GSN> = RoomGameController<GD, GSN>;
+
+/// Runs single-player practice sessions. Each session gets its own keys in
+/// the database so its answers and scores never reach the leaderboards.
+#[derive(Clone)]
+pub struct PracticeController<GD, QS, GSN>
+where
+    GD: GameDatabase + Send + Sync,
+    QS: QuestionStore,
+    GSN: GameStartNotifier,
+{
+    db: GD,
+    store: QS,
+    notifier: PhantomData<GSN>,
+}
+
+impl<GD, QS, GSN> PracticeController<GD, QS, GSN>
+where
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    QS: QuestionStore + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+{
+    pub fn new(db: GD, store: QS) -> Self {
+        Self {
+            db,
+            store,
+            notifier: PhantomData,
+        }
+    }
+
+    /// Prepares a session of random questions from the category in the
+    /// question bank. A category with no questions in the bank falls back to
+    /// the uploaded pack of that name, such as the built-in default pack. The
+    /// game starts as soon as the player connects to the returned controller.
+    pub async fn create_session(
+        &self,
+        user_id: String,
+        category: &str,
+    ) -> Result<PracticeGameController<GD, GSN>, PracticeError> {
+        use PracticeError::*;
+        let label = category.trim().to_lowercase();
+        let questions: Vec<_> = self
+            .store
+            .get_questions()
+            .await
+            .or(Err(DatabaseError))?
+            .into_iter()
+            .filter(|question| !question.retired && question.category == label)
+            .map(|question| question.question)
+            .collect();
+
+        let pack = if questions.is_empty() {
+            self.db
+                .get_pack(category)
+                .await
+                .or(Err(DatabaseError))?
+                .ok_or(CategoryNotFound)?
+        } else {
+            Game { questions }
+        };
+
+        let game = pack.sample(&mut rand::thread_rng(), PRACTICE_QUESTIONS);
+        if game.questions.is_empty() || game.validate().is_err() {
+            return Err(InvalidCategory);
+        }
+
+        let id = format!("practice-{}", generate_join_code(&mut rand::thread_rng()));
+        let notifier = GSN::default();
+        let db = RoomDatabase::new(self.db.clone(), id, game);
+        let controller = GameController::new(db.clone(), ManualStart, notifier.clone())
+            .with_lobby(PRACTICE_LOBBY);
+
+        tokio::spawn(
+            self.clone()
+                .run_session(db, controller.clone(), notifier, user_id),
+        );
+        Ok(controller)
+    }
+
+    /// Plays the session, then deletes everything it stored once the player
+    /// has left.
+    async fn run_session(
+        self,
+        db: RoomDatabase<GD>,
+        controller: PracticeGameController<GD, GSN>,
+        notifier: GSN,
+        user_id: String,
+    ) {
+        self.play_session(&controller, notifier, &user_id).await;
+
+        while controller.players() > 0 {
+            sleep(CHECK_INTERVAL).await;
+        }
+
+        if let Err(e) = db.clear().await {
+            log::error!("Failed to clear the practice session of {user_id}: {e}");
+        }
+    }
+
+    async fn play_session(
+        &self,
+        controller: &PracticeGameController<GD, GSN>,
+        notifier: GSN,
+        user_id: &str,
+    ) {
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        while !controller.is_connected(user_id).await {
+            if Instant::now() >= deadline {
+                return;
+            }
+            sleep(CHECK_INTERVAL).await;
+        }
+
+        // Gives the connection a moment to start waiting for the signal.
+        sleep(CHECK_INTERVAL).await;
+
+        let live = match controller.begin_game().await {
+            Some(live) => live,
+            None => {
+                log::error!("Failed to begin practice session for {user_id}");
+                return;
+            }
+        };
+
+        if notifier.send_signal().await.is_err() {
+            log::error!("Failed to signal practice start for {user_id}");
+            return;
+        }
+
+        while Instant::now() < live.ends_at() {
+            if !controller.is_connected(user_id).await {
+                return;
+            }
+            sleep(CHECK_INTERVAL).await;
+        }
+
+        let statuses = controller
+            .answer_statuses(user_id, &live, live.game.questions.len())
+            .await;
+
+        let mut stats = match self.db.get_practice_stats(user_id).await {
+            Ok(stats) => stats.unwrap_or_default(),
+            Err(e) => {
+                log::error!("Failed to get practice stats for {user_id}: {e}");
+                return;
+            }
+        };
+        stats.record(&statuses);
+
+        if let Err(e) = self.db.set_practice_stats(user_id, &stats).await {
+            log::error!("Failed to set practice stats for {user_id}: {e}");
+            return;
+        }
+
+        controller
+            .send_to(user_id, ServerMessage::PracticeStats { stats })
+            .await;
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PracticeError {
+    #[error("failed to get questions from the database")]
+    DatabaseError,
+    #[error("requested category was not found")]
+    CategoryNotFound,
+    #[error("requested category has no valid questions")]
+    InvalidCategory,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PracticeController, PracticeError, PRACTICE_QUESTIONS};
+    use crate::{
+        adapters::{GameMemoryDatabase, Notifier, QuestionMemoryStore, DEFAULT_PACK},
+        models::{AnswerStatus, BankQuestion, Difficulty, PracticeStats, Question},
+        ports::QuestionStore,
+    };
+
+    fn get_controller(
+        store: QuestionMemoryStore,
+    ) -> PracticeController<GameMemoryDatabase, QuestionMemoryStore, Notifier> {
+        PracticeController::new(GameMemoryDatabase::default(), store)
+    }
+
+    fn question(idx: usize, category: &str, retired: bool) -> BankQuestion {
+        BankQuestion {
+            id: format!("{category}-{idx}"),
+            question: Question::true_false(format!("{category} {idx}?"), true),
+            category: category.into(),
+            tags: Vec::new(),
+            difficulty: Difficulty::Easy,
+            source: None,
+            retired,
+            last_asked: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_categories() {
+        let controller = get_controller(QuestionMemoryStore::default());
+        let session = controller.create_session("user".into(), "missing").await;
+
+        assert!(matches!(session, Err(PracticeError::CategoryNotFound)));
+    }
+
+    #[tokio::test]
+    async fn creates_sessions_from_a_pack() {
+        let controller = get_controller(QuestionMemoryStore::default());
+        let session = controller
+            .create_session("user".into(), DEFAULT_PACK)
+            .await
+            .unwrap();
+
+        assert_eq!(session.players(), 0);
+    }
+
+    #[tokio::test]
+    async fn samples_the_category_from_the_bank() {
+        let store = QuestionMemoryStore::default();
+        for idx in 0..8 {
+            store
+                .set_question(&question(idx, "history", idx % 2 == 0))
+                .await
+                .unwrap();
+            store
+                .set_question(&question(idx, "science", false))
+                .await
+                .unwrap();
+        }
+        let controller = get_controller(store);
+
+        let session = controller
+            .create_session("user".into(), " History ")
+            .await
+            .unwrap();
+        let live = session.begin_game().await.unwrap();
+
+        assert_eq!(live.game.questions.len(), 4.min(PRACTICE_QUESTIONS));
+        assert!(live
+            .game
+            .questions
+            .iter()
+            .all(|question| question.question.starts_with("history")));
+    }
+
+    #[test]
+    fn records_sessions() {
+        let mut stats = PracticeStats::default();
+        stats.record(&[
+            AnswerStatus::Correct,
+            AnswerStatus::Close { rank: 2, points: 4 },
+            AnswerStatus::NoAnswer,
+        ]);
+
+        assert_eq!(
+            stats,
+            PracticeStats {
+                sessions: 1,
+                questions: 3,
+                correct: 1,
+                points: 14,
+            }
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::models::{ClientMessage, Encoding, ErrorCode, ServerMessage};
+use thiserror::Error;
+use warp::ws::Message;
+
+/// Version of the game protocol this server speaks. Clients that do not say
+/// `Hello` are assumed to speak the first version.
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Optional features a client can rely on once it has been welcomed.
+pub const CAPABILITIES: [&str; 9] = [
+    "resume",
+    "lifelines",
+    "teams",
+    "chat",
+    "reactions",
+    "shuffle",
+    "media",
+    "spectate",
+    "heartbeat",
+];
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ProtocolError {
+    #[error("protocol version {0} is not supported, supported versions are {MIN_PROTOCOL_VERSION} to {PROTOCOL_VERSION}")]
+    UnsupportedVersion(u32),
+}
+
+#[derive(Error, Debug)]
+pub enum CodecError {
+    #[error("failed to serialize message: {0}")]
+    Serialize(String),
+    #[error("failed to parse message: {0}")]
+    Parse(String),
+    #[error("expected a {0} frame")]
+    UnexpectedFrame(&'static str),
+}
+
+/// Answers a client's `Hello`, or tells it why it cannot play. The client
+/// gets the first encoding it asked for that the server knows, JSON if none.
+pub fn welcome(
+    protocol_version: u32,
+    client: &str,
+    encodings: &[String],
+) -> Result<ServerMessage, ProtocolError> {
+    if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&protocol_version) {
+        log::info!("Rejected {client} speaking protocol version {protocol_version}");
+        return Err(ProtocolError::UnsupportedVersion(protocol_version));
+    }
+
+    Ok(ServerMessage::Welcome {
+        protocol_version,
+        capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+        encoding: encodings
+            .iter()
+            .find_map(|name| Encoding::from_name(name))
+            .unwrap_or_default(),
+    })
+}
+
+pub fn encode_message(msg: &ServerMessage, encoding: Encoding) -> Result<Message, CodecError> {
+    let serialize = |e: &dyn std::fmt::Display| CodecError::Serialize(e.to_string());
+    match encoding {
+        Encoding::Json => serde_json::to_string(msg)
+            .map(Message::text)
+            .map_err(|e| serialize(&e)),
+        Encoding::MessagePack => rmp_serde::to_vec_named(msg)
+            .map(Message::binary)
+            .map_err(|e| serialize(&e)),
+        Encoding::Cbor => {
+            let mut buf = Vec::new();
+            ciborium::into_writer(msg, &mut buf)
+                .map(|()| Message::binary(buf))
+                .map_err(|e| serialize(&e))
+        }
+    }
+}
+
+/// Encodes a message for a client. A message that cannot be encoded is
+/// replaced by an error in the same encoding, so the client can still read it.
+pub fn encode_or_error(msg: &ServerMessage, encoding: Encoding) -> Message {
+    encode_message(msg, encoding).unwrap_or_else(|e| {
+        log::error!("{e}");
+        let error = ServerMessage::error(ErrorCode::EncodingFailed, e.to_string());
+        encode_message(&error, encoding).unwrap_or_else(|_| Message::close())
+    })
+}
+
+/// Reads a client frame. Text frames are always JSON, binary frames use the
+/// negotiated encoding.
+pub fn decode_message(msg: &Message, encoding: Encoding) -> Result<ClientMessage, CodecError> {
+    let parse = |e: &dyn std::fmt::Display| CodecError::Parse(e.to_string());
+
+    if let Ok(text) = msg.to_str() {
+        return serde_json::from_str(text).map_err(|e| parse(&e));
+    }
+
+    if !msg.is_binary() {
+        return Err(CodecError::UnexpectedFrame("text or binary"));
+    }
+
+    match encoding {
+        Encoding::Json => Err(CodecError::UnexpectedFrame("text")),
+        Encoding::MessagePack => rmp_serde::from_slice(msg.as_bytes()).map_err(|e| parse(&e)),
+        Encoding::Cbor => ciborium::from_reader(msg.as_bytes()).map_err(|e| parse(&e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_message, encode_message, welcome, ProtocolError, PROTOCOL_VERSION};
+    use crate::models::{ClientMessage, Encoding, ServerMessage};
+    use warp::ws::Message;
+
+    #[test]
+    fn welcomes_supported_versions_only() {
+        assert!(matches!(
+            welcome(PROTOCOL_VERSION, "elm", &[]),
+            Ok(ServerMessage::Welcome { protocol_version, .. }) if protocol_version == PROTOCOL_VERSION
+        ));
+        assert!(matches!(
+            welcome(PROTOCOL_VERSION + 1, "elm", &[]),
+            Err(ProtocolError::UnsupportedVersion(version)) if version == PROTOCOL_VERSION + 1
+        ));
+        assert!(welcome(0, "elm", &[]).is_err());
+    }
+
+    #[test]
+    fn negotiates_the_first_known_encoding() {
+        let encodings = ["zstd".to_string(), "cbor".into(), "msgpack".into()];
+
+        assert!(matches!(
+            welcome(PROTOCOL_VERSION, "ios", &encodings),
+            Ok(ServerMessage::Welcome {
+                encoding: Encoding::Cbor,
+                ..
+            })
+        ));
+        assert!(matches!(
+            welcome(PROTOCOL_VERSION, "ios", &encodings[..1]),
+            Ok(ServerMessage::Welcome {
+                encoding: Encoding::Json,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn reads_binary_frames_in_the_negotiated_encoding() {
+        let reaction = ClientMessage::React {
+            emoji: "🔥".into()
+        };
+        let frame = Message::binary(rmp_serde::to_vec_named(&reaction).unwrap());
+
+        assert!(matches!(
+            decode_message(&frame, Encoding::MessagePack),
+            Ok(ClientMessage::React { emoji }) if emoji == "🔥"
+        ));
+        assert!(decode_message(&frame, Encoding::Json).is_err());
+        assert!(matches!(
+            decode_message(&Message::text(r#"{"type":"LeaveTeam"}"#), Encoding::Cbor),
+            Ok(ClientMessage::LeaveTeam)
+        ));
+
+        let encoded = encode_message(&ServerMessage::WaitingForHost, Encoding::Cbor).unwrap();
+        assert!(encoded.is_binary());
+    }
+}
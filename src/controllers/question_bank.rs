@@ -0,0 +1,449 @@
+use crate::{
+    controllers::{normalize, read_pack, write_pack, PackError, RowError},
+    models::{BankQuestion, PackFormat},
+    ports::{IDGenerator, QuestionStore},
+    request::{QuestionRequest, QuestionRequestValidationError, QuestionSearchRequest},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, marker::PhantomData};
+use thiserror::Error;
+
+/// Categories and tags are kept lowercase and trimmed so that searching for
+/// "History" finds questions filed under " history".
+fn label(text: &str) -> String {
+    text.trim().to_lowercase()
+}
+
+fn labels(texts: &[String]) -> Vec<String> {
+    let mut labels: Vec<String> = texts
+        .iter()
+        .map(|text| label(text))
+        .filter(|text| !text.is_empty())
+        .collect();
+    labels.sort();
+    labels.dedup();
+    labels
+}
+
+fn matches(search: &QuestionSearchRequest, question: &BankQuestion) -> bool {
+    (search.include_retired || !question.retired)
+        && search
+            .category
+            .as_ref()
+            .is_none_or(|category| label(category) == question.category)
+        && search
+            .tag
+            .as_ref()
+            .is_none_or(|tag| question.tags.contains(&label(tag)))
+        && search
+            .difficulty
+            .is_none_or(|difficulty| difficulty == question.difficulty)
+        && search
+            .text
+            .as_ref()
+            .is_none_or(|text| normalize(&question.question.question).contains(&normalize(text)))
+}
+
+#[derive(Clone)]
+pub struct QuestionBankController<QS, I>
+where
+    QS: QuestionStore,
+    I: IDGenerator,
+{
+    store: QS,
+    _id: PhantomData<I>,
+}
+
+impl<QS, I> QuestionBankController<QS, I>
+where
+    QS: QuestionStore,
+    I: IDGenerator,
+{
+    pub fn new(store: QS) -> Self {
+        Self {
+            store,
+            _id: PhantomData,
+        }
+    }
+
+    pub async fn create(
+        &self,
+        request: QuestionRequest,
+    ) -> Result<BankQuestion, QuestionBankError> {
+        request.validate()?;
+        let question = bank_question(I::generate().await, request, false);
+
+        self.store
+            .set_question(&question)
+            .await
+            .or(Err(QuestionBankError::DatabaseError))?;
+        Ok(question)
+    }
+
+    /// Replaces the contents of a question. A retired question stays retired
+    /// and the question keeps when it was last asked.
+    pub async fn update(
+        &self,
+        id: &str,
+        request: QuestionRequest,
+    ) -> Result<BankQuestion, QuestionBankError> {
+        request.validate()?;
+        let current = self.get(id).await?;
+        let mut question = bank_question(current.id, request, current.retired);
+        question.last_asked = current.last_asked;
+
+        self.store
+            .set_question(&question)
+            .await
+            .or(Err(QuestionBankError::DatabaseError))?;
+        Ok(question)
+    }
+
+    pub async fn get(&self, id: &str) -> Result<BankQuestion, QuestionBankError> {
+        self.store
+            .get_question(id)
+            .await
+            .or(Err(QuestionBankError::DatabaseError))?
+            .ok_or(QuestionBankError::QuestionNotFound)
+    }
+
+    /// Questions matching the search, by category and then text.
+    pub async fn search(
+        &self,
+        search: &QuestionSearchRequest,
+    ) -> Result<Vec<BankQuestion>, QuestionBankError> {
+        let mut questions: Vec<BankQuestion> = self
+            .store
+            .get_questions()
+            .await
+            .or(Err(QuestionBankError::DatabaseError))?
+            .into_iter()
+            .filter(|question| matches(search, question))
+            .collect();
+
+        questions.sort_by(|a, b| {
+            (&a.category, &a.question.question).cmp(&(&b.category, &b.question.question))
+        });
+        Ok(questions)
+    }
+
+    /// Takes a question out of use without deleting it.
+    pub async fn retire(&self, id: &str) -> Result<BankQuestion, QuestionBankError> {
+        let mut question = self.get(id).await?;
+        question.retired = true;
+
+        self.store
+            .set_question(&question)
+            .await
+            .or(Err(QuestionBankError::DatabaseError))?;
+        Ok(question)
+    }
+
+    /// Adds the questions of a pack. Nothing is imported unless every row
+    /// is valid and new, so a pack can be fixed and imported again.
+    pub async fn import(
+        &self,
+        source: &str,
+        format: PackFormat,
+        dry_run: bool,
+    ) -> Result<ImportReport, QuestionBankError> {
+        let pack = read_pack(source, format);
+        let mut errors = pack.errors;
+
+        let mut known: HashMap<String, Option<usize>> = self
+            .store
+            .get_questions()
+            .await
+            .or(Err(QuestionBankError::DatabaseError))?
+            .into_iter()
+            .map(|question| (normalize(&question.question.question), None))
+            .collect();
+
+        let mut questions = Vec::new();
+        for row in pack.rows {
+            if let Err(err) = row.request.validate() {
+                errors.push(RowError::new(row.line, err));
+                continue;
+            }
+
+            let text = normalize(&row.request.question().question);
+            match known.get(&text) {
+                Some(Some(line)) => {
+                    errors.push(RowError::new(row.line, format!("duplicate of line {line}")));
+                    continue;
+                }
+                Some(None) => {
+                    errors.push(RowError::new(row.line, "already in the question bank"));
+                    continue;
+                }
+                None => known.insert(text, Some(row.line)),
+            };
+
+            questions.push(bank_question(I::generate().await, row.request, false));
+        }
+
+        errors.sort_by_key(|err| err.line);
+        let imported = errors.is_empty() && !dry_run;
+        if imported {
+            for question in &questions {
+                self.store
+                    .set_question(question)
+                    .await
+                    .or(Err(QuestionBankError::DatabaseError))?;
+            }
+        }
+
+        Ok(ImportReport {
+            dry_run,
+            imported,
+            questions,
+            errors,
+        })
+    }
+
+    /// Writes the questions matching the search as a pack. Ids are left out
+    /// so the pack can be imported into another bank.
+    pub async fn export(
+        &self,
+        search: &QuestionSearchRequest,
+        format: PackFormat,
+    ) -> Result<String, QuestionBankError> {
+        let questions: Vec<QuestionRequest> = self
+            .search(search)
+            .await?
+            .into_iter()
+            .map(|question| {
+                let request =
+                    QuestionRequest::new(question.question, question.category, question.difficulty)
+                        .with_tags(question.tags);
+                match question.source {
+                    Some(source) => request.with_source(source),
+                    None => request,
+                }
+            })
+            .collect();
+
+        Ok(write_pack(&questions, format)?)
+    }
+}
+
+/// What importing a pack did, or would do on a dry run. `questions` are the
+/// valid rows, which are only added when `imported` is set.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub imported: bool,
+    pub questions: Vec<BankQuestion>,
+    pub errors: Vec<RowError>,
+}
+
+fn bank_question(id: String, request: QuestionRequest, retired: bool) -> BankQuestion {
+    BankQuestion {
+        id,
+        question: request.question().clone(),
+        category: label(request.category()),
+        tags: labels(request.tags()),
+        difficulty: request.difficulty(),
+        source: request
+            .source()
+            .map(str::trim)
+            .filter(|source| !source.is_empty())
+            .map(String::from),
+        retired,
+        last_asked: None,
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum QuestionBankError {
+    #[error("failed to get questions from the database")]
+    DatabaseError,
+    #[error("requested question was not found")]
+    QuestionNotFound,
+    #[error("validation error: {0}")]
+    RequestValidationError(#[from] QuestionRequestValidationError),
+    #[error("{0}")]
+    PackError(#[from] PackError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QuestionBankController, QuestionBankError};
+    use crate::{
+        adapters::{QuestionMemoryStore, UuidGenerator},
+        controllers::RowError,
+        models::{Difficulty, PackFormat, Question},
+        request::{QuestionRequest, QuestionRequestValidationError, QuestionSearchRequest},
+    };
+
+    fn get_controller() -> QuestionBankController<QuestionMemoryStore, UuidGenerator> {
+        QuestionBankController::new(QuestionMemoryStore::default())
+    }
+
+    fn request(text: &str, category: &str, difficulty: Difficulty) -> QuestionRequest {
+        QuestionRequest::new(
+            Question::true_false(text.into(), true),
+            category.into(),
+            difficulty,
+        )
+    }
+
+    #[tokio::test]
+    async fn creates_and_edits_questions() {
+        let controller = get_controller();
+
+        let created = controller
+            .create(
+                request("Is Rome in Italy?", " Geography ", Difficulty::Easy).with_tags(vec![
+                    "Europe".into(),
+                    "europe".into(),
+                    "cities".into(),
+                ]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(created.category, "geography");
+        assert_eq!(created.tags, vec!["cities", "europe"]);
+
+        let updated = controller
+            .update(
+                &created.id,
+                request(
+                    "Is Rome the capital of Italy?",
+                    "geography",
+                    Difficulty::Medium,
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(updated.id, created.id);
+        assert_eq!(controller.get(&created.id).await.unwrap(), updated);
+
+        assert_eq!(
+            controller
+                .update("missing", request("?", "geography", Difficulty::Easy))
+                .await,
+            Err(QuestionBankError::QuestionNotFound)
+        );
+        assert_eq!(
+            controller
+                .create(request("Is it?", " ", Difficulty::Easy))
+                .await,
+            Err(QuestionBankError::RequestValidationError(
+                QuestionRequestValidationError::CategoryEmpty
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn searches_and_retires_questions() {
+        let controller = get_controller();
+        let rome = controller
+            .create(request("Is Rome in Italy?", "geography", Difficulty::Easy))
+            .await
+            .unwrap();
+        controller
+            .create(request(
+                "Is Zürich in France?",
+                "geography",
+                Difficulty::Hard,
+            ))
+            .await
+            .unwrap();
+        controller
+            .create(request(
+                "Did Rome fall in 476?",
+                "history",
+                Difficulty::Hard,
+            ))
+            .await
+            .unwrap();
+
+        let search = |search: QuestionSearchRequest| {
+            let controller = controller.clone();
+            async move { controller.search(&search).await.unwrap().len() }
+        };
+
+        let geography = QuestionSearchRequest {
+            category: Some("Geography".into()),
+            ..Default::default()
+        };
+        assert_eq!(search(geography.clone()).await, 2);
+        assert_eq!(
+            search(QuestionSearchRequest {
+                text: Some("zurich".into()),
+                ..Default::default()
+            })
+            .await,
+            1
+        );
+        assert_eq!(
+            search(QuestionSearchRequest {
+                difficulty: Some(Difficulty::Hard),
+                ..Default::default()
+            })
+            .await,
+            2
+        );
+
+        assert!(controller.retire(&rome.id).await.unwrap().retired);
+        assert_eq!(search(geography.clone()).await, 1);
+        assert_eq!(
+            search(QuestionSearchRequest {
+                include_retired: true,
+                ..geography
+            })
+            .await,
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn imports_packs_without_duplicates() {
+        let controller = get_controller();
+        controller
+            .create(request("Is Rome in Italy?", "geography", Difficulty::Easy))
+            .await
+            .unwrap();
+
+        let csv = "question,kind,options,answer,category,difficulty\n\
+                   Is Oslo in Norway?,choice,True|False,1,geography,Easy\n\
+                   is rome in  italy?,choice,True|False,1,geography,Easy\n\
+                   Is Oslo in   Norway?,choice,True|False,1,geography,Easy\n";
+        let report = controller
+            .import(csv, PackFormat::Csv, false)
+            .await
+            .unwrap();
+        assert!(!report.imported);
+        assert_eq!(
+            report.errors,
+            vec![
+                RowError::new(3, "already in the question bank"),
+                RowError::new(4, "duplicate of line 2"),
+            ]
+        );
+
+        let csv = csv.lines().take(2).collect::<Vec<_>>().join("\n");
+        let report = controller
+            .import(&csv, PackFormat::Csv, true)
+            .await
+            .unwrap();
+        assert!(report.errors.is_empty() && !report.imported);
+        assert_eq!(report.questions.len(), 1);
+
+        let report = controller
+            .import(&csv, PackFormat::Csv, false)
+            .await
+            .unwrap();
+        assert!(report.imported);
+        let all = QuestionSearchRequest::default();
+        assert_eq!(controller.search(&all).await.unwrap().len(), 2);
+
+        let exported = controller.export(&all, PackFormat::Yaml).await.unwrap();
+        let report = controller
+            .import(&exported, PackFormat::Yaml, true)
+            .await
+            .unwrap();
+        assert_eq!(report.errors.len(), 2);
+    }
+}
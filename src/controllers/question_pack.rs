@@ -0,0 +1,600 @@
+use crate::{
+    models::{Difficulty, OptionIndex, PackFormat, Question, QuestionKind, DEFAULT_MAX_DISTANCE},
+    request::QuestionRequest,
+};
+use serde::{
+    de::{self, DeserializeSeed, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_json::value::RawValue;
+use std::fmt;
+use thiserror::Error;
+
+/// Separates the options, accepted answers and tags kept in a single CSV cell.
+const CSV_LIST_SEPARATOR: char = '|';
+/// Bank fields of the questions in a JSON or YAML pack that sets none.
+const DEFAULT_CATEGORY: &str = "general";
+const DEFAULT_DIFFICULTY: Difficulty = Difficulty::Medium;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PackError {
+    #[error("failed to write pack: {0}")]
+    Write(String),
+}
+
+/// Why a question in a pack cannot be imported, and the line it starts on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RowError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl RowError {
+    pub fn new(line: usize, message: impl fmt::Display) -> Self {
+        Self {
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// A question read from a pack, not yet validated.
+pub struct PackRow {
+    pub line: usize,
+    pub request: QuestionRequest,
+}
+
+/// The questions that could be read from a pack, and the rows that could not.
+#[derive(Default)]
+pub struct ParsedPack {
+    pub rows: Vec<PackRow>,
+    pub errors: Vec<RowError>,
+}
+
+impl ParsedPack {
+    fn push<E: fmt::Display>(&mut self, line: usize, row: Result<QuestionRequest, E>) {
+        match row {
+            Ok(request) => self.rows.push(PackRow { line, request }),
+            Err(err) => self.errors.push(RowError::new(line, err)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Pack<'a> {
+    questions: &'a [QuestionRequest],
+}
+
+/// A JSON pack. A plain `Game` is one too, its questions take the bank fields
+/// set next to `questions`, or the defaults.
+#[derive(Deserialize)]
+struct JsonPack<'a> {
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    difficulty: Option<Difficulty>,
+    #[serde(borrow)]
+    questions: Vec<&'a RawValue>,
+}
+
+/// A question of a JSON or YAML pack, whose bank fields override the pack's.
+#[derive(Deserialize)]
+struct PackQuestion {
+    #[serde(flatten)]
+    question: Question,
+    category: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    difficulty: Option<Difficulty>,
+    source: Option<String>,
+}
+
+impl PackQuestion {
+    fn into_request(self, category: &str, difficulty: Difficulty) -> QuestionRequest {
+        let request = QuestionRequest::new(
+            self.question,
+            self.category.unwrap_or_else(|| category.into()),
+            self.difficulty.unwrap_or(difficulty),
+        )
+        .with_tags(self.tags);
+
+        match self.source {
+            Some(source) => request.with_source(source),
+            None => request,
+        }
+    }
+}
+
+/// A YAML pack, which sets the same bank fields next to `questions` as a
+/// JSON pack.
+#[derive(Deserialize)]
+struct YamlPack {
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    difficulty: Option<Difficulty>,
+    questions: Vec<YamlQuestion>,
+}
+
+/// A question of a YAML pack, kept as a value until the pack's bank fields
+/// are known, and the line it starts on.
+struct YamlQuestion {
+    line: Option<usize>,
+    value: serde_yaml::Value,
+}
+
+impl<'de> Deserialize<'de> for YamlQuestion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(YamlQuestionVisitor)
+    }
+}
+
+struct YamlQuestionVisitor;
+
+impl<'de> Visitor<'de> for YamlQuestionVisitor {
+    type Value = YamlQuestion;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a question")
+    }
+
+    /// serde_yaml only locates errors, so the first key is read with a seed
+    /// that fails on it, and the rest of the question is read as usual.
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<YamlQuestion, A::Error> {
+        let mut key = None;
+        let line = match map.next_key_seed(FirstKey(&mut key)) {
+            Ok(_) => None,
+            Err(err) if key.is_some() => error_line(&err.to_string()),
+            Err(err) => return Err(err),
+        };
+
+        let mut question = serde_yaml::Mapping::new();
+        if let Some(key) = key {
+            question.insert(key.into(), map.next_value()?);
+        }
+        while let Some((key, value)) = map.next_entry()? {
+            question.insert(key, value);
+        }
+
+        Ok(YamlQuestion {
+            line,
+            value: question.into(),
+        })
+    }
+}
+
+/// Takes the key it is given and fails, so that the error is located at it.
+struct FirstKey<'a>(&'a mut Option<String>);
+
+impl<'de> DeserializeSeed<'de> for FirstKey<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FirstKey<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a field name")
+    }
+
+    fn visit_str<E: de::Error>(self, key: &str) -> Result<(), E> {
+        *self.0 = Some(key.into());
+        Err(E::custom("located"))
+    }
+}
+
+/// The line of a located serde_yaml error, which the error only gives out
+/// as text from inside a deserializer, ending in "at line {line} column
+/// {column}".
+fn error_line(message: &str) -> Option<usize> {
+    let (_, location) = message.rsplit_once(" at line ")?;
+    location.split(' ').next()?.parse().ok()
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CsvKind {
+    Choice,
+    Text,
+    Number,
+}
+
+/// A CSV row. Choice answers are the number of the right option, counting
+/// from 1, text answers are the accepted answers, matched within
+/// `max_distance` typos.
+#[derive(Deserialize, Serialize)]
+struct CsvRow {
+    question: String,
+    kind: CsvKind,
+    #[serde(default)]
+    options: String,
+    answer: String,
+    #[serde(default)]
+    tolerance: Option<f64>,
+    #[serde(default)]
+    max_distance: Option<usize>,
+    category: String,
+    difficulty: Difficulty,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    source: String,
+}
+
+#[derive(Error, Debug)]
+enum CsvRowError {
+    #[error("choice answer must be an option number from 1 to {0}")]
+    InvalidOption(usize),
+    #[error("number answer is not a number")]
+    InvalidNumber,
+    #[error("number question needs a tolerance")]
+    MissingTolerance,
+    #[error("media cannot be written to CSV, use JSON or YAML")]
+    Media,
+}
+
+fn split_list(cell: &str) -> Vec<String> {
+    cell.split(CSV_LIST_SEPARATOR)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn join_list(items: &[String]) -> String {
+    items.join(&CSV_LIST_SEPARATOR.to_string())
+}
+
+impl CsvRow {
+    fn into_request(self) -> Result<QuestionRequest, CsvRowError> {
+        let kind = match self.kind {
+            CsvKind::Choice => {
+                let options = split_list(&self.options);
+                let answer_idx = self
+                    .answer
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|number| number.checked_sub(1))
+                    .filter(|idx| *idx < options.len())
+                    .and_then(OptionIndex::from_index)
+                    .ok_or(CsvRowError::InvalidOption(options.len()))?;
+
+                QuestionKind::Choice {
+                    options,
+                    answer_idx,
+                    option_media: Vec::new(),
+                }
+            }
+            CsvKind::Text => QuestionKind::Text {
+                accepted_answers: split_list(&self.answer),
+                max_distance: self.max_distance.unwrap_or(DEFAULT_MAX_DISTANCE),
+            },
+            CsvKind::Number => QuestionKind::Number {
+                answer: self
+                    .answer
+                    .trim()
+                    .parse()
+                    .or(Err(CsvRowError::InvalidNumber))?,
+                tolerance: self.tolerance.ok_or(CsvRowError::MissingTolerance)?,
+            },
+        };
+
+        let question = Question {
+            question: self.question,
+            media: None,
+            kind,
+        };
+        let request = QuestionRequest::new(question, self.category, self.difficulty)
+            .with_tags(split_list(&self.tags));
+
+        Ok(match self.source.trim() {
+            "" => request,
+            source => request.with_source(source.into()),
+        })
+    }
+
+    fn from_request(request: &QuestionRequest) -> Result<Self, CsvRowError> {
+        let question = request.question();
+        if question.media.is_some() {
+            return Err(CsvRowError::Media);
+        }
+
+        let (kind, options, answer, tolerance, max_distance) = match &question.kind {
+            QuestionKind::Choice {
+                options,
+                answer_idx,
+                option_media,
+            } => {
+                if option_media.iter().any(Option::is_some) {
+                    return Err(CsvRowError::Media);
+                }
+                let answer = (answer_idx.index() + 1).to_string();
+                (CsvKind::Choice, join_list(options), answer, None, None)
+            }
+            QuestionKind::Text {
+                accepted_answers,
+                max_distance,
+            } => (
+                CsvKind::Text,
+                String::new(),
+                join_list(accepted_answers),
+                None,
+                Some(*max_distance),
+            ),
+            QuestionKind::Number { answer, tolerance } => (
+                CsvKind::Number,
+                String::new(),
+                answer.to_string(),
+                Some(*tolerance),
+                None,
+            ),
+        };
+
+        Ok(Self {
+            question: question.question.clone(),
+            kind,
+            options,
+            answer,
+            tolerance,
+            max_distance,
+            category: request.category().into(),
+            difficulty: request.difficulty(),
+            tags: join_list(request.tags()),
+            source: request.source().unwrap_or_default().into(),
+        })
+    }
+}
+
+fn line_at(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count() + 1
+}
+
+fn read_json(source: &str) -> ParsedPack {
+    let mut pack = ParsedPack::default();
+    let raw = match serde_json::from_str::<JsonPack>(source) {
+        Ok(raw) => raw,
+        Err(err) => {
+            pack.errors.push(RowError::new(err.line(), err));
+            return pack;
+        }
+    };
+    let category = raw.category.as_deref().unwrap_or(DEFAULT_CATEGORY);
+    let difficulty = raw.difficulty.unwrap_or(DEFAULT_DIFFICULTY);
+
+    for question in raw.questions {
+        // the raw value borrows from the source, so its address is its offset
+        let offset = question.get().as_ptr() as usize - source.as_ptr() as usize;
+        let row = serde_json::from_str::<PackQuestion>(question.get())
+            .map(|question| question.into_request(category, difficulty));
+        pack.push(line_at(source, offset), row);
+    }
+    pack
+}
+
+fn read_yaml(source: &str) -> ParsedPack {
+    let mut pack = ParsedPack::default();
+    let raw = match serde_yaml::from_str::<YamlPack>(source) {
+        Ok(raw) => raw,
+        Err(err) => {
+            let line = err.location().map_or(1, |location| location.line());
+            pack.errors.push(RowError::new(line, err));
+            return pack;
+        }
+    };
+    let category = raw.category.as_deref().unwrap_or(DEFAULT_CATEGORY);
+    let difficulty = raw.difficulty.unwrap_or(DEFAULT_DIFFICULTY);
+
+    // an empty question has no key to locate, it goes after the one before
+    let mut line = 1;
+    for question in raw.questions {
+        line = question.line.unwrap_or(line);
+        let row = serde_yaml::from_value::<PackQuestion>(question.value)
+            .map(|question| question.into_request(category, difficulty));
+        pack.push(line, row);
+    }
+    pack
+}
+
+fn read_csv(source: &str) -> ParsedPack {
+    let mut pack = ParsedPack::default();
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(source.as_bytes());
+
+    let headers = match reader.byte_headers() {
+        Ok(headers) => headers.clone(),
+        Err(err) => {
+            pack.errors.push(RowError::new(1, err));
+            return pack;
+        }
+    };
+
+    for record in reader.byte_records() {
+        match record {
+            Ok(record) => {
+                let line = record.position().map_or(1, |position| position.line()) as usize;
+                let row = record
+                    .deserialize::<CsvRow>(Some(&headers))
+                    .map_err(|err| err.to_string())
+                    .and_then(|row| row.into_request().map_err(|err| err.to_string()));
+                pack.push(line, row);
+            }
+            Err(err) => {
+                let line = err.position().map_or(1, |position| position.line()) as usize;
+                pack.errors.push(RowError::new(line, err));
+            }
+        }
+    }
+    pack
+}
+
+pub fn read_pack(source: &str, format: PackFormat) -> ParsedPack {
+    match format {
+        PackFormat::Json => read_json(source),
+        PackFormat::Csv => read_csv(source),
+        PackFormat::Yaml => read_yaml(source),
+    }
+}
+
+pub fn write_pack(questions: &[QuestionRequest], format: PackFormat) -> Result<String, PackError> {
+    let write = |e: &dyn fmt::Display| PackError::Write(e.to_string());
+    let pack = Pack { questions };
+
+    match format {
+        PackFormat::Json => serde_json::to_string_pretty(&pack).map_err(|e| write(&e)),
+        PackFormat::Yaml => serde_yaml::to_string(&pack).map_err(|e| write(&e)),
+        PackFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for request in questions {
+                let row = CsvRow::from_request(request).map_err(|e| {
+                    write(&format!(
+                        "question \"{}\": {e}",
+                        request.question().question
+                    ))
+                })?;
+                writer.serialize(row).map_err(|e| write(&e))?;
+            }
+
+            let bytes = writer.into_inner().map_err(|e| write(&e))?;
+            String::from_utf8(bytes).map_err(|e| write(&e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_pack, write_pack, RowError};
+    use crate::models::PackFormat;
+    use crate::{
+        models::{Difficulty, Question, QuestionKind},
+        request::QuestionRequest,
+    };
+
+    fn questions() -> Vec<QuestionRequest> {
+        let strict = Question {
+            kind: QuestionKind::Text {
+                accepted_answers: vec!["Nile".into()],
+                max_distance: 0,
+            },
+            ..Question::text("Longest river?".into(), Vec::new())
+        };
+
+        vec![
+            QuestionRequest::new(
+                Question::true_false("Is Rome in Italy?".into(), true),
+                "geography".into(),
+                Difficulty::Easy,
+            )
+            .with_tags(vec!["europe".into(), "cities".into()]),
+            QuestionRequest::new(
+                Question::text("Capital of Ethiopia?".into(), vec!["Addis Ababa".into()]),
+                "geography".into(),
+                Difficulty::Hard,
+            )
+            .with_source("atlas".into()),
+            QuestionRequest::new(strict, "geography".into(), Difficulty::Medium),
+        ]
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        for format in [PackFormat::Json, PackFormat::Csv, PackFormat::Yaml] {
+            let written = write_pack(&questions(), format).unwrap();
+            let pack = read_pack(&written, format);
+
+            assert!(pack.errors.is_empty(), "{format:?}: {:?}", pack.errors);
+            let read: Vec<_> = pack.rows.into_iter().map(|row| row.request).collect();
+            assert_eq!(
+                serde_json::to_value(read).unwrap(),
+                serde_json::to_value(questions()).unwrap(),
+                "{format:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_the_line_of_each_bad_row() {
+        let csv = "question,kind,options,answer,tolerance,category,difficulty\n\
+                   Is Rome in Italy?,choice,True|False,1,,geography,Easy\n\
+                   Is Oslo in Italy?,choice,True|False,3,,geography,Easy\n\
+                   How many moons?,number,,two,,space,Medium\n";
+        let pack = read_pack(csv, PackFormat::Csv);
+        assert_eq!(pack.rows.len(), 1);
+        assert_eq!(
+            pack.errors,
+            vec![
+                RowError::new(3, "choice answer must be an option number from 1 to 2"),
+                RowError::new(4, "number answer is not a number"),
+            ]
+        );
+
+        let yaml = "questions:\n\
+                    - question: Is Rome in Italy?\n  options: [\"True\", \"False\"]\n  answer_idx: One\n  category: geography\n  difficulty: Easy\n\
+                    # no question\n\
+                    - options: [\"True\", \"False\"]\n  answer_idx: Two\n  category: geography\n";
+        let pack = read_pack(yaml, PackFormat::Yaml);
+        assert_eq!(pack.rows[0].line, 2);
+        assert_eq!(pack.errors.len(), 1);
+        assert_eq!(pack.errors[0].line, 8);
+
+        let flow = "questions: [{question: \"?\"},\n  {question: Is Rome in Italy?, options: [\"True\", \"False\"], answer_idx: One}]";
+        let pack = read_pack(flow, PackFormat::Yaml);
+        assert_eq!((pack.errors[0].line, pack.rows[0].line), (1, 2));
+
+        let json = "{\"questions\": [\n  {\"question\": \"?\"}\n]}";
+        assert_eq!(read_pack(json, PackFormat::Json).errors[0].line, 2);
+    }
+
+    #[test]
+    fn reads_plain_games_as_json_packs() {
+        let game = r#"{"questions": [
+            {"question": "Is Rome in Italy?", "options": ["True", "False"], "answer_idx": "One"}
+        ]}"#;
+        let pack = read_pack(game, PackFormat::Json);
+        assert!(pack.errors.is_empty(), "{:?}", pack.errors);
+        assert_eq!(pack.rows[0].request.category(), "general");
+        assert_eq!(pack.rows[0].request.difficulty(), Difficulty::Medium);
+
+        let pack = r#"{"category": "geography", "difficulty": "Easy", "questions": [
+            {"question": "Is Rome in Italy?", "options": ["True", "False"], "answer_idx": "One"},
+            {"question": "Is Oslo in Italy?", "options": ["True", "False"], "answer_idx": "Two", "difficulty": "Hard"}
+        ]}"#;
+        let pack = read_pack(pack, PackFormat::Json);
+        assert!(pack.errors.is_empty(), "{:?}", pack.errors);
+        assert_eq!(pack.rows[0].request.category(), "geography");
+        assert_eq!(pack.rows[0].request.difficulty(), Difficulty::Easy);
+        assert_eq!(pack.rows[1].request.difficulty(), Difficulty::Hard);
+    }
+
+    #[test]
+    fn yaml_packs_set_bank_fields_like_json_packs() {
+        let pack = "category: geography\n\
+                    difficulty: Easy\n\
+                    questions:\n\
+                    - question: Is Rome in Italy?\n  options: [\"True\", \"False\"]\n  answer_idx: One\n\
+                    - question: Is Oslo in Italy?\n  options: [\"True\", \"False\"]\n  answer_idx: Two\n  difficulty: Hard\n";
+        let pack = read_pack(pack, PackFormat::Yaml);
+        assert!(pack.errors.is_empty(), "{:?}", pack.errors);
+        assert_eq!(pack.rows[0].request.category(), "geography");
+        assert_eq!(pack.rows[0].request.difficulty(), Difficulty::Easy);
+        assert_eq!(pack.rows[1].request.difficulty(), Difficulty::Hard);
+        assert_eq!((pack.rows[0].line, pack.rows[1].line), (4, 7));
+
+        let game = "questions:\n- question: Is Rome in Italy?\n  options: [\"True\", \"False\"]\n  answer_idx: One\n";
+        let pack = read_pack(game, PackFormat::Yaml);
+        assert!(pack.errors.is_empty(), "{:?}", pack.errors);
+        assert_eq!(pack.rows[0].request.category(), "general");
+        assert_eq!(pack.rows[0].request.difficulty(), Difficulty::Medium);
+    }
+}
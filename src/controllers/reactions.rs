@@ -0,0 +1,85 @@
+use crate::models::ServerMessage;
+use std::{
+    collections::{BTreeMap, HashSet},
+    mem,
+    time::Duration,
+};
+use thiserror::Error;
+
+pub const REACTIONS: [&str; 6] = ["👏", "😂", "😮", "😢", "🔥", "🎉"];
+pub const REACTION_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ReactionError {
+    #[error("reaction is not allowed")]
+    NotAllowed,
+    #[error("reactions are only open while an answer is revealed")]
+    NotRevealing,
+    #[error("only one reaction per second is allowed")]
+    TooFast,
+}
+
+/// Reactions collected since the last broadcast. Players get one reaction
+/// per interval so a batch never holds more reactions than players.
+#[derive(Debug, Default)]
+pub struct Reactions {
+    counts: BTreeMap<String, u32>,
+    reacted: HashSet<String>,
+}
+
+impl Reactions {
+    /// Counts the reaction. Returns `true` for the first reaction of an
+    /// interval, when the caller has to schedule the next broadcast.
+    pub fn add(&mut self, user_id: &str, emoji: &str) -> Result<bool, ReactionError> {
+        if !REACTIONS.contains(&emoji) {
+            return Err(ReactionError::NotAllowed);
+        }
+
+        if !self.reacted.insert(user_id.into()) {
+            return Err(ReactionError::TooFast);
+        }
+
+        *self.counts.entry(emoji.into()).or_default() += 1;
+        Ok(self.reacted.len() == 1)
+    }
+
+    /// Ends the interval, returning the batch to broadcast if there is one.
+    pub fn take(&mut self) -> Option<ServerMessage> {
+        self.reacted.clear();
+        let counts = mem::take(&mut self.counts);
+
+        if counts.is_empty() {
+            return None;
+        }
+
+        Some(ServerMessage::Reactions { counts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReactionError, Reactions};
+    use crate::models::ServerMessage;
+
+    #[test]
+    fn batches_reactions_per_interval() {
+        let mut reactions = Reactions::default();
+
+        assert_eq!(reactions.add("a", "🔥"), Ok(true));
+        assert_eq!(reactions.add("b", "🔥"), Ok(false));
+        assert_eq!(reactions.add("c", "👏"), Ok(false));
+        assert_eq!(reactions.add("a", "👏"), Err(ReactionError::TooFast));
+        assert_eq!(reactions.add("d", "💩"), Err(ReactionError::NotAllowed));
+
+        match reactions.take() {
+            Some(ServerMessage::Reactions { counts }) => {
+                assert_eq!(counts.get("🔥"), Some(&2));
+                assert_eq!(counts.get("👏"), Some(&1));
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+
+        assert!(reactions.take().is_none());
+        assert_eq!(reactions.add("a", "👏"), Ok(true));
+    }
+}
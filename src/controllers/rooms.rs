@@ -0,0 +1,504 @@
+use crate::{
+    controllers::{ChatFilter, GameController},
+    models::{Answer, AnswerStatus, Game, Lifeline, PracticeStats},
+    ports::{GameDatabase, GameStartNotifier, JobSchedular},
+    request::{CreateRoomRequest, CreateRoomValidationError},
+};
+use async_trait::async_trait;
+use rand::Rng;
+use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::{sync::Mutex, time::Instant};
+
+pub const JOIN_CODE_LENGTH: usize = 6;
+const JOIN_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const ROOM_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Generates a join code that avoids easily confused characters like `0`/`O`
+/// and `1`/`I`.
+pub fn generate_join_code<R: Rng>(rng: &mut R) -> String {
+    (0..JOIN_CODE_LENGTH)
+        .map(|_| JOIN_CODE_ALPHABET[rng.gen_range(0..JOIN_CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// Keeps a room's answers, scores and lifelines apart from every other
+/// room's by prefixing the keys it stores them under. Ratings and practice
+/// stats are not prefixed as they follow a player from game to game.
+#[derive(Clone)]
+pub struct RoomDatabase<GD> {
+    db: GD,
+    room: String,
+    game: Game,
+}
+
+impl<GD> RoomDatabase<GD> {
+    pub fn new(db: GD, room: String, game: Game) -> Self {
+        Self { db, room, game }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("room:{}:{key}", self.room)
+    }
+}
+
+impl<GD> RoomDatabase<GD>
+where
+    GD: GameDatabase + Send + Sync,
+{
+    /// Deletes everything the room stored.
+    pub async fn clear(&self) -> Result<(), GD::Error> {
+        self.clear_prefixed("").await
+    }
+}
+
+#[async_trait]
+impl<GD> GameDatabase for RoomDatabase<GD>
+where
+    GD: GameDatabase + Send + Sync,
+{
+    type Error = GD::Error;
+
+    async fn get_game(&self) -> Result<Option<Game>, Self::Error> {
+        Ok(Some(self.game.clone()))
+    }
+
+    /// A room always plays the game it was created with.
+    async fn set_game(&self, _game: &Game) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn clear_game(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn get_pack(&self, name: &str) -> Result<Option<Game>, Self::Error> {
+        self.db.get_pack(name).await
+    }
+
+    async fn set_pack(&self, name: &str, game: &Game) -> Result<(), Self::Error> {
+        self.db.set_pack(name, game).await
+    }
+
+    async fn set_answer(
+        &self,
+        id: &str,
+        question: &str,
+        answer: Answer,
+    ) -> Result<(), Self::Error> {
+        self.db
+            .set_answer(&self.key(id), &self.key(question), answer)
+            .await
+    }
+
+    async fn get_answer(&self, id: &str, question: &str) -> Result<Option<Answer>, Self::Error> {
+        self.db.get_answer(&self.key(id), &self.key(question)).await
+    }
+
+    async fn get_answers(&self, question: &str) -> Result<Vec<Answer>, Self::Error> {
+        self.db.get_answers(&self.key(question)).await
+    }
+
+    async fn set_answer_status(
+        &self,
+        id: &str,
+        question: &str,
+        answer_status: &AnswerStatus,
+    ) -> Result<(), Self::Error> {
+        self.db
+            .set_answer_status(&self.key(id), &self.key(question), answer_status)
+            .await
+    }
+
+    async fn get_answer_status(
+        &self,
+        id: &str,
+        question: &str,
+    ) -> Result<Option<AnswerStatus>, Self::Error> {
+        self.db
+            .get_answer_status(&self.key(id), &self.key(question))
+            .await
+    }
+
+    async fn get_answers_statuses(&self, id: &str) -> Result<Vec<AnswerStatus>, Self::Error> {
+        self.db.get_answers_statuses(&self.key(id)).await
+    }
+
+    async fn set_score(&self, id: &str, score: u32) -> Result<(), Self::Error> {
+        self.db.set_score(&self.key(id), score).await
+    }
+
+    async fn add_lifeline(
+        &self,
+        id: &str,
+        question: &str,
+        lifeline: Lifeline,
+    ) -> Result<(), Self::Error> {
+        self.db
+            .add_lifeline(&self.key(id), &self.key(question), lifeline)
+            .await
+    }
+
+    async fn get_lifelines(&self, id: &str) -> Result<Vec<(String, Lifeline)>, Self::Error> {
+        let prefix = self.key("");
+        let lifelines = self.db.get_lifelines(&self.key(id)).await?;
+
+        Ok(lifelines
+            .into_iter()
+            .map(
+                |(question, lifeline)| match question.strip_prefix(&prefix) {
+                    Some(question) => (question.into(), lifeline),
+                    None => (question, lifeline),
+                },
+            )
+            .collect())
+    }
+
+    async fn clear_lifelines(&self, id: &str) -> Result<(), Self::Error> {
+        self.db.clear_lifelines(&self.key(id)).await
+    }
+
+    async fn clear_prefixed(&self, prefix: &str) -> Result<(), Self::Error> {
+        self.db.clear_prefixed(&self.key(prefix)).await
+    }
+
+    async fn get_rating(&self, id: &str) -> Result<Option<u32>, Self::Error> {
+        self.db.get_rating(id).await
+    }
+
+    async fn set_rating(&self, id: &str, rating: u32) -> Result<(), Self::Error> {
+        self.db.set_rating(id, rating).await
+    }
+
+    async fn get_practice_stats(&self, id: &str) -> Result<Option<PracticeStats>, Self::Error> {
+        self.db.get_practice_stats(id).await
+    }
+
+    async fn set_practice_stats(&self, id: &str, stats: &PracticeStats) -> Result<(), Self::Error> {
+        self.db.set_practice_stats(id, stats).await
+    }
+}
+
+/// Rooms have no schedule; their owner starts the game.
+#[derive(Clone, Default)]
+pub struct ManualStart;
+
+#[async_trait]
+impl JobSchedular for ManualStart {
+    type Error = Infallible;
+
+    async fn time_till_game(&mut self) -> Result<Option<Duration>, Self::Error> {
+        Ok(None)
+    }
+}
+
+pub type RoomGameController<GD, GSN> = GameController<RoomDatabase<GD>, ManualStart, GSN>;
+
+#[derive(Clone)]
+struct Room<GD, GSN>
+where
+    GD: GameDatabase + Send + Sync,
+    GSN: GameStartNotifier,
+{
+    owner: String,
+    pack: String,
+    created_at: Instant,
+    db: RoomDatabase<GD>,
+    notifier: GSN,
+    controller: RoomGameController<GD, GSN>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomInfo {
+    pub code: String,
+    pub pack: String,
+    pub players: u32,
+}
+
+#[derive(Clone)]
+pub struct RoomsController<GD, GSN>
+where
+    GD: GameDatabase + Send + Sync,
+    GSN: GameStartNotifier,
+{
+    db: GD,
+    rooms: Arc<Mutex<HashMap<String, Room<GD, GSN>>>>,
+    chat_filter: ChatFilter,
+}
+
+impl<GD, GSN> RoomsController<GD, GSN>
+where
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+{
+    pub fn new(db: GD) -> Self {
+        Self {
+            db,
+            rooms: Arc::new(Mutex::new(HashMap::new())),
+            chat_filter: ChatFilter::default(),
+        }
+    }
+
+    pub fn with_chat_filter(mut self, chat_filter: ChatFilter) -> Self {
+        self.chat_filter = chat_filter;
+        self
+    }
+
+    pub async fn create_room(
+        &self,
+        owner: String,
+        request: CreateRoomRequest,
+    ) -> Result<String, RoomError> {
+        use RoomError::*;
+        request.validate()?;
+
+        let game = self
+            .db
+            .get_pack(request.pack())
+            .await
+            .or(Err(DatabaseError))?
+            .ok_or(PackNotFound)?;
+
+        game.validate().or(Err(InvalidPack))?;
+
+        let mut rooms = self.rooms.lock().await;
+        let code = loop {
+            let code = generate_join_code(&mut rand::thread_rng());
+            if !rooms.contains_key(&code) {
+                break code;
+            }
+        };
+
+        let notifier = GSN::default();
+        let db = RoomDatabase::new(self.db.clone(), code.clone(), game);
+        let controller = GameController::new(db.clone(), ManualStart, notifier.clone())
+            .with_team_scoring(request.team_scoring())
+            .with_shuffle(request.shuffle())
+            .with_chat_filter(self.chat_filter.clone());
+        controller.add_moderator(&owner).await;
+
+        let room = Room {
+            owner,
+            pack: request.pack().into(),
+            created_at: Instant::now(),
+            db,
+            notifier,
+            controller,
+        };
+
+        rooms.insert(code.clone(), room);
+        Ok(code)
+    }
+
+    pub async fn room_info(&self, code: &str) -> Result<RoomInfo, RoomError> {
+        let code = code.to_uppercase();
+        let room = self.get_room(&code).await?;
+
+        Ok(RoomInfo {
+            players: room.controller.players(),
+            pack: room.pack,
+            code,
+        })
+    }
+
+    /// Returns the game controller players of the room connect to.
+    pub async fn join(&self, code: &str) -> Result<RoomGameController<GD, GSN>, RoomError> {
+        Ok(self.get_room(code).await?.controller)
+    }
+
+    pub async fn start_game(&self, code: &str, user_id: &str) -> Result<(), RoomError> {
+        use RoomError::*;
+        let room = self.get_room(code).await?;
+
+        if room.owner != user_id {
+            return Err(NotOwner);
+        }
+
+        // spectators are waiting too, but cannot play the game on their own
+        if room.controller.players() == 0 {
+            return Err(NoPlayers);
+        }
+
+        room.notifier.send_signal().await.or(Err(NoPlayers))
+    }
+
+    /// Closes expired rooms every few minutes, for as long as the server
+    /// runs.
+    pub async fn prune_periodically(self) {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            self.prune_expired().await;
+        }
+    }
+
+    /// Forgets the rooms that outlived ROOM_LIFETIME and deletes what they
+    /// stored.
+    pub async fn prune_expired(&self) {
+        let expired: Vec<_> = {
+            let mut rooms = self.rooms.lock().await;
+            let codes: Vec<String> = rooms
+                .iter()
+                .filter(|(_, room)| room.created_at.elapsed() >= ROOM_LIFETIME)
+                .map(|(code, _)| code.clone())
+                .collect();
+
+            codes
+                .into_iter()
+                .filter_map(|code| rooms.remove_entry(&code))
+                .collect()
+        };
+
+        for (code, room) in expired {
+            if let Err(e) = room.db.clear().await {
+                log::error!("Failed to clear room {code}: {e}");
+            }
+        }
+    }
+
+    async fn get_room(&self, code: &str) -> Result<Room<GD, GSN>, RoomError> {
+        let rooms = self.rooms.lock().await;
+        rooms
+            .get(&code.to_uppercase())
+            .filter(|room| room.created_at.elapsed() < ROOM_LIFETIME)
+            .cloned()
+            .ok_or(RoomError::RoomNotFound)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RoomError {
+    #[error("failed to get question pack from the database")]
+    DatabaseError,
+    #[error("requested question pack was not found")]
+    PackNotFound,
+    #[error("requested question pack is invalid")]
+    InvalidPack,
+    #[error("requested room was not found")]
+    RoomNotFound,
+    #[error("only the room owner can start the game")]
+    NotOwner,
+    #[error("no players are connected to the room")]
+    NoPlayers,
+    #[error("validation error")]
+    RequestValidationError(#[from] CreateRoomValidationError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_join_code, RoomDatabase, RoomError, RoomsController, JOIN_CODE_LENGTH};
+    use crate::{
+        adapters::{GameMemoryDatabase, Notifier, DEFAULT_PACK},
+        models::{Answer, Game, Lifeline, OptionIndex},
+        ports::GameDatabase,
+        request::CreateRoomRequest,
+    };
+
+    fn get_controller() -> RoomsController<GameMemoryDatabase, Notifier> {
+        RoomsController::new(GameMemoryDatabase::default())
+    }
+
+    #[test]
+    fn join_codes_are_short_and_unambiguous() {
+        let code = generate_join_code(&mut rand::thread_rng());
+
+        assert_eq!(code.len(), JOIN_CODE_LENGTH);
+        assert!(!code.contains(['0', 'O', '1', 'I']));
+    }
+
+    #[tokio::test]
+    async fn create_and_join_a_room() {
+        let controller = get_controller();
+
+        let code = controller
+            .create_room("owner".into(), CreateRoomRequest::new(DEFAULT_PACK.into()))
+            .await
+            .unwrap();
+
+        let info = controller.room_info(&code.to_lowercase()).await.unwrap();
+        assert_eq!(info.code, code);
+        assert_eq!(info.players, 0);
+        assert!(controller.join(&code).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn can_not_create_a_room_with_an_unknown_pack() {
+        let controller = get_controller();
+
+        let res = controller
+            .create_room("owner".into(), CreateRoomRequest::new("unknown".into()))
+            .await;
+        assert_eq!(res.err(), Some(RoomError::PackNotFound));
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_can_start_the_game() {
+        let controller = get_controller();
+
+        let code = controller
+            .create_room("owner".into(), CreateRoomRequest::new(DEFAULT_PACK.into()))
+            .await
+            .unwrap();
+
+        let res = controller.start_game(&code, "someone else").await;
+        assert_eq!(res.err(), Some(RoomError::NotOwner));
+
+        let res = controller.start_game(&code, "owner").await;
+        assert_eq!(res.err(), Some(RoomError::NoPlayers));
+
+        let res = controller.start_game("NOROOM", "owner").await;
+        assert_eq!(res.err(), Some(RoomError::RoomNotFound));
+    }
+
+    #[tokio::test]
+    async fn rooms_do_not_share_answers() {
+        let db = GameMemoryDatabase::default();
+        let game = Game { questions: vec![] };
+        let first = RoomDatabase::new(db.clone(), "FIRST".into(), game.clone());
+        let second = RoomDatabase::new(db, "SECOND".into(), game);
+        let answer = Answer::Choice(OptionIndex::One);
+
+        first
+            .set_answer("user", "question", answer.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(first.get_answer("user", "question").await, Ok(Some(answer)));
+        assert_eq!(second.get_answer("user", "question").await, Ok(None));
+        assert_eq!(second.get_answers("question").await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn clearing_a_room_keeps_other_rooms() {
+        let db = GameMemoryDatabase::default();
+        let game = Game { questions: vec![] };
+        let first = RoomDatabase::new(db.clone(), "FIRST".into(), game.clone());
+        let second = RoomDatabase::new(db, "SECOND".into(), game);
+        let answer = Answer::Choice(OptionIndex::One);
+
+        for room in [&first, &second] {
+            room.set_answer("user", "question", answer.clone())
+                .await
+                .unwrap();
+            room.add_lifeline("user", "question", Lifeline::Skip)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            first.get_lifelines("user").await,
+            Ok(vec![("question".into(), Lifeline::Skip)])
+        );
+
+        first.clear().await.unwrap();
+
+        assert_eq!(first.get_answer("user", "question").await, Ok(None));
+        assert_eq!(first.get_lifelines("user").await, Ok(vec![]));
+        assert_eq!(
+            second.get_answer("user", "question").await,
+            Ok(Some(answer))
+        );
+        assert_eq!(second.get_lifelines("user").await.map(|l| l.len()), Ok(1));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::models::{Answer, OptionIndex, Question, QuestionView};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+/// FNV-1a over the parts, used instead of `DefaultHasher` so that seeds do
+/// not change between builds.
+fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for part in parts {
+        for byte in part.bytes().chain([0xff]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// The order in which one player sees the options of each question. It is
+/// seeded from the game and user ids, so every connection of the player
+/// agrees on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shuffle {
+    seed: u64,
+}
+
+impl Shuffle {
+    pub fn new(game_id: &str, user_id: &str) -> Self {
+        Self {
+            seed: fnv1a(&[game_id, user_id]),
+        }
+    }
+
+    pub fn option_order(&self, question: &Question) -> OptionOrder {
+        let mut order: Vec<usize> = (0..question.options().len()).collect();
+        let seed = self.seed ^ fnv1a(&[&question.question]);
+        order.shuffle(&mut StdRng::seed_from_u64(seed));
+        OptionOrder(order)
+    }
+}
+
+/// Canonical index of the option shown in each position. The default order
+/// leaves options where they are.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptionOrder(Vec<usize>);
+
+impl OptionOrder {
+    pub fn to_canonical(&self, shown: &OptionIndex) -> OptionIndex {
+        self.0
+            .get(shown.index())
+            .and_then(|idx| OptionIndex::from_index(*idx))
+            .unwrap_or_else(|| shown.clone())
+    }
+
+    pub fn to_shown(&self, canonical: &OptionIndex) -> OptionIndex {
+        self.0
+            .iter()
+            .position(|idx| *idx == canonical.index())
+            .and_then(OptionIndex::from_index)
+            .unwrap_or_else(|| canonical.clone())
+    }
+
+    pub fn answer_to_canonical(&self, answer: Answer) -> Answer {
+        match answer {
+            Answer::Choice(shown) => Answer::Choice(self.to_canonical(&shown)),
+            answer => answer,
+        }
+    }
+
+    pub fn answer_to_shown(&self, answer: Answer) -> Answer {
+        match answer {
+            Answer::Choice(canonical) => Answer::Choice(self.to_shown(&canonical)),
+            answer => answer,
+        }
+    }
+
+    /// Puts per-option values, like answer counts, in the shown order.
+    pub fn arrange<T: Clone>(&self, items: &[T]) -> Vec<T> {
+        if self.0.is_empty() {
+            return items.to_vec();
+        }
+        self.0
+            .iter()
+            .filter_map(|idx| items.get(*idx).cloned())
+            .collect()
+    }
+
+    pub fn view(&self, question: &Question) -> QuestionView {
+        let mut view = QuestionView::from(question);
+        view.options = self.arrange(&view.options);
+
+        if !view.option_media.is_empty() {
+            view.option_media.resize(question.options().len(), None);
+            view.option_media = self.arrange(&view.option_media);
+        }
+
+        view
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OptionOrder, Shuffle};
+    use crate::models::{Answer, OptionIndex, Question};
+
+    fn sample_question() -> Question {
+        Question::choice(
+            "Which is the third?".into(),
+            vec!["A".into(), "B".into(), "C".into(), "D".into()],
+            OptionIndex::Three,
+        )
+    }
+
+    #[test]
+    fn is_deterministic_per_player() {
+        let shuffle = Shuffle::new("game", "user");
+
+        assert_eq!(shuffle, Shuffle::new("game", "user"));
+        assert_ne!(shuffle, Shuffle::new("game", "other"));
+        assert_ne!(shuffle, Shuffle::new("other", "user"));
+        assert_eq!(
+            shuffle.option_order(&sample_question()),
+            shuffle.option_order(&sample_question())
+        );
+    }
+
+    #[test]
+    fn maps_shown_options_back_to_canonical() {
+        let question = sample_question();
+        let order = Shuffle::new("game", "user").option_order(&question);
+        let view = order.view(&question);
+
+        let shown = order.to_shown(&OptionIndex::Three);
+        assert_eq!(view.options[shown.index()], "C");
+        assert_eq!(order.to_canonical(&shown), OptionIndex::Three);
+        assert_eq!(
+            order.answer_to_canonical(Answer::Choice(shown)),
+            Answer::Choice(OptionIndex::Three)
+        );
+        assert_eq!(
+            order.arrange(&[0, 0, 7, 0])[order.to_shown(&OptionIndex::Three).index()],
+            7
+        );
+    }
+
+    #[test]
+    fn default_order_keeps_options_in_place() {
+        let order = OptionOrder::default();
+
+        assert_eq!(order.to_canonical(&OptionIndex::Two), OptionIndex::Two);
+        assert_eq!(order.to_shown(&OptionIndex::Four), OptionIndex::Four);
+        assert_eq!(
+            order.view(&sample_question()).options,
+            vec!["A", "B", "C", "D"]
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{
+    controllers::GameController,
+    models::ClientMessage,
+    ports::{GameDatabase, GameStartNotifier, JobSchedular},
+};
+use futures_util::{Sink, Stream, StreamExt};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use thiserror::Error;
+use tokio::sync::{
+    mpsc::{channel, unbounded_channel, Sender, UnboundedSender},
+    Mutex,
+};
+use tokio_stream::wrappers::{ReceiverStream, UnboundedReceiverStream};
+use warp::ws::Message;
+
+/// Frames an event stream may fall behind by before its session ends.
+pub const SSE_BUFFER: usize = 64;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SseError {
+    #[error("no event stream is open for this user")]
+    NoStream,
+    #[error("event stream has been closed")]
+    Closed,
+    #[error("failed to encode message")]
+    InvalidMessage,
+}
+
+/// Gives an event stream and the messages posted next to it the shape of a
+/// websocket, so the game controller runs the same session over both.
+struct SseSocket {
+    incoming: UnboundedReceiverStream<Message>,
+    outgoing: Sender<Message>,
+}
+
+impl Stream for SseSocket {
+    type Item = Result<Message, warp::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.incoming.poll_next_unpin(cx).map(|msg| msg.map(Ok))
+    }
+}
+
+impl Sink<Message> for SseSocket {
+    type Error = SseError;
+
+    fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// A client that stops reading its stream is dropped rather than having
+    /// its frames pile up.
+    fn start_send(self: Pin<&mut Self>, msg: Message) -> Result<(), Self::Error> {
+        self.outgoing.try_send(msg).or(Err(SseError::Closed))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Server-sent event streams of the players that cannot open a websocket.
+/// Their messages arrive as separate requests and are routed to the session
+/// of the user's open stream.
+#[derive(Clone, Default)]
+pub struct SseSessions {
+    inboxes: Arc<Mutex<HashMap<String, UnboundedSender<Message>>>>,
+}
+
+impl SseSessions {
+    /// Starts a game session for the user. The returned frames are the ones
+    /// a websocket would get, ending with a close frame.
+    pub async fn connect<GD, JS, GSN>(
+        &self,
+        controller: GameController<GD, JS, GSN>,
+        user_id: String,
+    ) -> impl Stream<Item = Message>
+    where
+        GD: GameDatabase + Send + Sync + Clone + 'static,
+        JS: JobSchedular + Send + Sync + Clone + 'static,
+        GSN: GameStartNotifier + Send + Sync + Clone + 'static,
+    {
+        let (inbox, incoming) = unbounded_channel();
+        let (outgoing, frames) = channel(SSE_BUFFER);
+        let socket = SseSocket {
+            incoming: UnboundedReceiverStream::new(incoming),
+            outgoing,
+        };
+
+        self.inboxes
+            .lock()
+            .await
+            .insert(user_id.clone(), inbox.clone());
+
+        let inboxes = self.inboxes.clone();
+        let own_inbox = inbox.clone();
+        tokio::spawn(async move {
+            controller.start(user_id.clone(), socket).await;
+
+            let mut inboxes = inboxes.lock().await;
+            if inboxes
+                .get(&user_id)
+                .is_some_and(|inbox| inbox.same_channel(&own_inbox))
+            {
+                inboxes.remove(&user_id);
+            }
+        });
+
+        // Events have no pongs. A ping taken by the response body is the
+        // closest sign of a live client, as a stalled one stops taking them.
+        ReceiverStream::new(frames).inspect(move |msg| {
+            if msg.is_ping() {
+                let _ = inbox.send(Message::pong(Vec::new()));
+            }
+        })
+    }
+
+    /// Hands a posted message to the user's session, as if it came over the
+    /// websocket. Event streams only carry JSON, so other encodings are
+    /// never negotiated.
+    pub async fn post(&self, user_id: &str, msg: ClientMessage) -> Result<(), SseError> {
+        let msg = match msg {
+            ClientMessage::Hello {
+                protocol_version,
+                client,
+                ..
+            } => ClientMessage::Hello {
+                protocol_version,
+                client,
+                encodings: Vec::new(),
+            },
+            msg => msg,
+        };
+
+        let text = serde_json::to_string(&msg).or(Err(SseError::InvalidMessage))?;
+        self.inboxes
+            .lock()
+            .await
+            .get(user_id)
+            .ok_or(SseError::NoStream)?
+            .send(Message::text(text))
+            .or(Err(SseError::Closed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SseError, SseSessions, SseSocket};
+    use crate::{
+        adapters::{GameMemoryDatabase, Notifier},
+        controllers::{GameController, ManualStart},
+        models::ClientMessage,
+    };
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::sync::mpsc::{channel, unbounded_channel};
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use warp::ws::Message;
+
+    #[tokio::test]
+    async fn routes_posted_messages_to_the_open_stream() {
+        let sessions = SseSessions::default();
+        let message = || ClientMessage::Chat { text: "hi".into() };
+        assert_eq!(
+            sessions.post("user", message()).await,
+            Err(SseError::NoStream)
+        );
+
+        let controller =
+            GameController::new(GameMemoryDatabase::default(), ManualStart, Notifier::new());
+        let mut frames = Box::pin(sessions.connect(controller, "user".into()).await);
+
+        let first = frames.next().await.unwrap();
+        assert!(first.to_str().unwrap().contains("WaitingForHost"));
+
+        sessions.post("user", message()).await.unwrap();
+        let chat = frames.next().await.unwrap();
+        assert!(chat.to_str().unwrap().contains(r#""text":"hi""#));
+    }
+
+    #[tokio::test]
+    async fn ends_streams_that_fall_behind() {
+        let (outgoing, mut frames) = channel(1);
+        let mut socket = SseSocket {
+            incoming: UnboundedReceiverStream::new(unbounded_channel().1),
+            outgoing,
+        };
+
+        socket.send(Message::text("first")).await.unwrap();
+        assert_eq!(
+            socket.send(Message::text("second")).await,
+            Err(SseError::Closed)
+        );
+
+        frames.recv().await.unwrap();
+        socket.send(Message::text("third")).await.unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+use crate::models::{Answer, AnswerStatus, TeamScoring};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+pub const MAX_TEAM_NAME_LENGTH: usize = 24;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TeamError {
+    #[error("teams can only be changed before the game starts")]
+    GameInProgress,
+    #[error("team name is empty")]
+    NameEmpty,
+    #[error("team name is longer than {MAX_TEAM_NAME_LENGTH} characters")]
+    NameTooLong,
+    #[error("requested team name already exists")]
+    NameTaken,
+    #[error("requested team was not found")]
+    TeamNotFound,
+}
+
+/// Team membership for one game, keyed by team name.
+#[derive(Debug, Clone, Default)]
+pub struct Teams {
+    teams: BTreeMap<String, Vec<String>>,
+}
+
+impl Teams {
+    pub fn create(&mut self, name: &str, user_id: &str) -> Result<(), TeamError> {
+        use TeamError::*;
+        let name = name.trim();
+
+        if name.is_empty() {
+            return Err(NameEmpty);
+        }
+
+        if name.chars().count() > MAX_TEAM_NAME_LENGTH {
+            return Err(NameTooLong);
+        }
+
+        if self.teams.contains_key(name) {
+            return Err(NameTaken);
+        }
+
+        self.leave(user_id);
+        self.teams.insert(name.into(), vec![user_id.into()]);
+        Ok(())
+    }
+
+    pub fn join(&mut self, name: &str, user_id: &str) -> Result<(), TeamError> {
+        let name = name.trim();
+
+        if !self.teams.contains_key(name) {
+            return Err(TeamError::TeamNotFound);
+        }
+
+        self.leave(user_id);
+        if let Some(members) = self.teams.get_mut(name) {
+            members.push(user_id.into());
+        }
+        Ok(())
+    }
+
+    /// Removes the user from their team, dropping the team once it is empty.
+    /// Returns the name of the team they left.
+    pub fn leave(&mut self, user_id: &str) -> Option<String> {
+        let name = self.team_of(user_id)?.to_string();
+
+        if let Some(members) = self.teams.get_mut(&name) {
+            members.retain(|member| member != user_id);
+            if members.is_empty() {
+                self.teams.remove(&name);
+            }
+        }
+
+        Some(name)
+    }
+
+    pub fn team_of(&self, user_id: &str) -> Option<&str> {
+        self.teams
+            .iter()
+            .find(|(_, members)| members.iter().any(|member| member == user_id))
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn members(&self, name: &str) -> &[String] {
+        self.teams.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Vec<String>)> {
+        self.teams.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.teams.is_empty()
+    }
+}
+
+/// Pools the members' results for one question into the team's points. Each
+/// result is the member's graded answer, if they gave one.
+pub fn team_points(scoring: TeamScoring, results: &[(AnswerStatus, Option<Answer>)]) -> u32 {
+    let statuses = results.iter().map(|(status, _)| status);
+
+    match scoring {
+        TeamScoring::Sum => statuses.map(AnswerStatus::points).sum(),
+        TeamScoring::Best => statuses.map(AnswerStatus::points).max().unwrap_or_default(),
+        TeamScoring::MajorityVote => {
+            let mut votes: Vec<(&Answer, &AnswerStatus, usize)> = Vec::new();
+
+            for (status, answer) in results {
+                let answer = match (status, answer) {
+                    (AnswerStatus::Skipped, _) | (_, None) => continue,
+                    (_, Some(answer)) => answer,
+                };
+
+                match votes.iter_mut().find(|(a, _, _)| *a == answer) {
+                    Some((_, _, count)) => *count += 1,
+                    None => votes.push((answer, status, 1)),
+                }
+            }
+
+            // the team's answer is the one most members gave, and ties go to
+            // the lower scoring answer
+            votes
+                .into_iter()
+                .max_by(|(_, a, a_votes), (_, b, b_votes)| {
+                    a_votes
+                        .cmp(b_votes)
+                        .then_with(|| b.points().cmp(&a.points()))
+                })
+                .map(|(_, status, _)| status.points())
+                .unwrap_or_default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{team_points, TeamError, Teams};
+    use crate::models::{Answer, AnswerStatus, OptionIndex, TeamScoring};
+
+    #[test]
+    fn create_join_and_leave_teams() {
+        let mut teams = Teams::default();
+
+        assert!(teams.create("Red", "a").is_ok());
+        assert!(teams.join("Red", "b").is_ok());
+        assert_eq!(teams.create("Red", "c"), Err(TeamError::NameTaken));
+        assert_eq!(teams.join("Blue", "c"), Err(TeamError::TeamNotFound));
+        assert_eq!(teams.members("Red"), ["a", "b"]);
+
+        assert!(teams.create("Blue", "a").is_ok());
+        assert_eq!(teams.team_of("a"), Some("Blue"));
+        assert_eq!(teams.members("Red"), ["b"]);
+
+        assert_eq!(teams.leave("b"), Some("Red".into()));
+        assert!(teams.members("Red").is_empty());
+    }
+
+    #[test]
+    fn validates_team_names() {
+        let mut teams = Teams::default();
+
+        assert_eq!(teams.create("  ", "a"), Err(TeamError::NameEmpty));
+        assert_eq!(
+            teams.create(&"x".repeat(25), "a"),
+            Err(TeamError::NameTooLong)
+        );
+    }
+
+    #[test]
+    fn pools_team_points() {
+        use AnswerStatus::*;
+        let choice = |idx| Some(Answer::Choice(idx));
+        let results = [
+            (Correct, choice(OptionIndex::One)),
+            (Correct, choice(OptionIndex::One)),
+            (Incorrect, choice(OptionIndex::Two)),
+            (Close { rank: 2, points: 4 }, Some(Answer::Number(4.0))),
+        ];
+
+        assert_eq!(team_points(TeamScoring::Sum, &results), 24);
+        assert_eq!(team_points(TeamScoring::Best, &results), 10);
+        assert_eq!(team_points(TeamScoring::MajorityVote, &results), 10);
+        assert_eq!(team_points(TeamScoring::Best, &[]), 0);
+    }
+
+    #[test]
+    fn majority_votes_on_answers() {
+        use AnswerStatus::*;
+        let choice = |idx| Some(Answer::Choice(idx));
+
+        // members who did not answer do not vote
+        let results = [(Correct, choice(OptionIndex::One)), (NoAnswer, None)];
+        assert_eq!(team_points(TeamScoring::MajorityVote, &results), 10);
+
+        let results = [
+            (Correct, choice(OptionIndex::One)),
+            (Incorrect, choice(OptionIndex::Two)),
+            (Incorrect, choice(OptionIndex::Two)),
+        ];
+        assert_eq!(team_points(TeamScoring::MajorityVote, &results), 0);
+
+        let results = [
+            (Correct, choice(OptionIndex::One)),
+            (Correct, choice(OptionIndex::One)),
+            (Incorrect, choice(OptionIndex::Two)),
+            (Skipped, choice(OptionIndex::Two)),
+        ];
+        assert_eq!(team_points(TeamScoring::MajorityVote, &results), 10);
+    }
+}
@@ -14,6 +14,7 @@ where
     I: IDGenerator,
 {
     db: D,
+    admins: Vec<String>,
     _data: (PhantomData<H>, PhantomData<T>, PhantomData<I>),
 }
 
@@ -27,10 +28,22 @@ where
     pub fn new(db: D) -> Self {
         Self {
             db,
+            admins: Vec::new(),
             _data: (PhantomData, PhantomData, PhantomData),
         }
     }
 
+    /// Ids of the users allowed to moderate and manage the game. Usernames
+    /// would not do, since anyone can register a name nobody has taken yet.
+    pub fn with_admins(mut self, admins: Vec<String>) -> Self {
+        self.admins = admins;
+        self
+    }
+
+    pub fn is_admin(&self, id: &str) -> bool {
+        self.admins.iter().any(|admin| admin == id)
+    }
+
     pub async fn register(&self, request: RegisterRequest) -> Result<String, RegistrationError> {
         use RegistrationError::*;
         request.validate()?;
@@ -266,4 +279,20 @@ mod tests {
         let decoded_user = controller.authorize(token).await;
         assert!(decoded_user.is_ok());
     }
+
+    #[tokio::test]
+    async fn admins_are_listed_by_id_not_username() {
+        let controller = get_controller();
+        let token = controller
+            .register(RegisterRequest::new("admin".into(), "123".into()))
+            .await
+            .unwrap();
+        let id = controller.authorize(token).await.unwrap();
+
+        let controller = controller.with_admins(vec!["admin".into()]);
+        assert!(!controller.is_admin(&id));
+
+        let controller = controller.with_admins(vec![id.clone()]);
+        assert!(controller.is_admin(&id));
+    }
 }
@@ -1,13 +1,22 @@
 use crate::{
-    controllers::{GameController, UsersController},
+    controllers::{
+        DuelsController, GameController, PracticeController, QuestionBankController,
+        QuestionBankError, RoomError, RoomsController, SseError, SseSessions, UsersController,
+    },
+    models::ClientMessage,
     ports::{
-        GameDatabase, GameStartNotifier, Hasher, IDGenerator, JobSchedular, TokenGenerator,
-        UsersDatabase,
+        GameDatabase, GameStartNotifier, Hasher, IDGenerator, JobSchedular, QuestionStore,
+        TokenGenerator, UsersDatabase,
+    },
+    request::{
+        CreateRoomRequest, LoginRequest, QuestionPackRequest, QuestionRequest,
+        QuestionSearchRequest, RegisterRequest, TokenRequest,
     },
-    request::{LoginRequest, RegisterRequest},
 };
+use futures_util::StreamExt;
 use serde::Deserialize;
-use warp::{hyper::StatusCode, reply::Reply, ws::Ws, Filter};
+use std::convert::Infallible;
+use warp::{hyper::StatusCode, reply::Reply, sse::Event, ws::Ws, Filter};
 
 type WarpResult<T> = Result<T, std::convert::Infallible>;
 
@@ -34,6 +43,94 @@ pub fn with_game_controller<
     warp::any().map(move || controller.clone())
 }
 
+pub fn with_rooms_controller<
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+>(
+    controller: RoomsController<GD, GSN>,
+) -> impl Filter<Extract = (RoomsController<GD, GSN>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || controller.clone())
+}
+
+pub fn with_duels_controller<
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+>(
+    controller: DuelsController<GD, GSN>,
+) -> impl Filter<Extract = (DuelsController<GD, GSN>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || controller.clone())
+}
+
+pub fn with_practice_controller<
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    QS: QuestionStore + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+>(
+    controller: PracticeController<GD, QS, GSN>,
+) -> impl Filter<Extract = (PracticeController<GD, QS, GSN>,), Error = std::convert::Infallible> + Clone
+{
+    warp::any().map(move || controller.clone())
+}
+
+pub fn with_question_bank_controller<
+    QS: QuestionStore + Send + Sync + Clone + 'static,
+    I: IDGenerator + Send + Sync + Clone + 'static,
+>(
+    controller: QuestionBankController<QS, I>,
+) -> impl Filter<Extract = (QuestionBankController<QS, I>,), Error = std::convert::Infallible> + Clone
+{
+    warp::any().map(move || controller.clone())
+}
+
+pub fn with_sse_sessions(
+    sessions: SseSessions,
+) -> impl Filter<Extract = (SseSessions,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || sessions.clone())
+}
+
+/// Extracts the token from an `Authorization: Bearer <token>` header.
+pub fn with_bearer_token() -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::header::<String>("authorization").and_then(|header: String| async move {
+        match header.strip_prefix("Bearer ") {
+            Some(token) => Ok(token.to_string()),
+            None => Err(warp::reject::not_found()),
+        }
+    })
+}
+
+/// Lets a request through only if the `token` in its query string belongs
+/// to a registered user. Without `required` every request gets through.
+pub fn with_auth<
+    D: UsersDatabase + Clone + Send + Sync + 'static,
+    H: Hasher + Clone + Send + Sync + 'static,
+    T: TokenGenerator + Clone + Send + Sync + 'static,
+    I: IDGenerator + Clone + Send + Sync + 'static,
+>(
+    controller: UsersController<D, H, T, I>,
+    required: bool,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::query::<TokenRequest>()
+        .and_then(move |request: TokenRequest| {
+            let controller = controller.clone();
+            async move {
+                if !required {
+                    return Ok(());
+                }
+
+                let authorized = match request.token {
+                    Some(token) => controller.authorize(token).await.is_ok(),
+                    None => false,
+                };
+                if !authorized {
+                    log::error!("Unauthenticated user");
+                    return Err(warp::reject::not_found());
+                }
+                Ok(())
+            }
+        })
+        .untuple_one()
+}
+
 pub fn with_json_body<T>() -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone
 where
     T: for<'de> Deserialize<'de> + Send,
@@ -115,10 +212,568 @@ pub async fn websocket_handler<
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let authorized = controller.authorize(token).await;
     match authorized {
-        Ok(id) => Ok(ws.on_upgrade(move |socket| game_controller.start(id, socket))),
+        Ok(id) => {
+            if controller.is_admin(&id) {
+                game_controller.add_moderator(&id).await;
+            }
+            Ok(ws.on_upgrade(move |socket| game_controller.start(id, socket)))
+        }
         Err(_) => {
             eprintln!("Unauthenticated user");
             Err(warp::reject::not_found())
         }
     }
 }
+
+/// Streams the game as server-sent events, for clients that cannot open a
+/// websocket. Their messages go to `sse_message_handler`.
+pub async fn sse_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    JS: JobSchedular + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Send + Sync + Clone + 'static,
+>(
+    token: String,
+    controller: UsersController<D, H, T, I>,
+    game_controller: GameController<GD, JS, GSN>,
+    sessions: SseSessions,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let id = match controller.authorize(token).await {
+        Ok(id) => id,
+        Err(_) => {
+            log::error!("Unauthenticated user");
+            return Err(warp::reject::not_found());
+        }
+    };
+
+    if controller.is_admin(&id) {
+        game_controller.add_moderator(&id).await;
+    }
+
+    let events = sessions
+        .connect(game_controller, id)
+        .await
+        .take_while(|frame| futures_util::future::ready(!frame.is_close()))
+        .filter_map(|frame| async move {
+            if frame.is_ping() {
+                Some(Ok::<_, Infallible>(Event::default().comment("ping")))
+            } else {
+                frame
+                    .to_str()
+                    .ok()
+                    .map(|text| Ok(Event::default().data(text)))
+            }
+        });
+
+    Ok(warp::sse::reply(events))
+}
+
+pub async fn sse_message_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+>(
+    controller: UsersController<D, H, T, I>,
+    sessions: SseSessions,
+    token: String,
+    msg: ClientMessage,
+) -> WarpResult<impl Reply> {
+    let user_id = match controller.authorize(token).await {
+        Ok(user_id) => user_id,
+        Err(_) => return Ok(unauthorized_reply()),
+    };
+
+    let (status, body) = match sessions.post(&user_id, msg).await {
+        Ok(()) => (StatusCode::ACCEPTED, serde_json::json!({ "status": "OK" })),
+        Err(err) => {
+            let status = match err {
+                SseError::NoStream | SseError::Closed => StatusCode::NOT_FOUND,
+                SseError::InvalidMessage => StatusCode::BAD_REQUEST,
+            };
+            let body = serde_json::json!({
+                "status": "ERROR",
+                "message": err.to_string(),
+            });
+            (status, body)
+        }
+    };
+
+    Ok(warp::reply::with_status(warp::reply::json(&body), status))
+}
+
+fn room_error_reply(err: RoomError) -> warp::reply::WithStatus<warp::reply::Json> {
+    let status = match err {
+        RoomError::RoomNotFound | RoomError::PackNotFound => StatusCode::NOT_FOUND,
+        RoomError::NotOwner => StatusCode::FORBIDDEN,
+        RoomError::RequestValidationError(_) | RoomError::InvalidPack | RoomError::NoPlayers => {
+            StatusCode::BAD_REQUEST
+        }
+        RoomError::DatabaseError => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    let response = warp::reply::json(&serde_json::json!({
+        "status": "ERROR",
+        "message": err.to_string(),
+    }));
+
+    warp::reply::with_status(response, status)
+}
+
+fn unauthorized_reply() -> warp::reply::WithStatus<warp::reply::Json> {
+    let response = warp::reply::json(&serde_json::json!({
+        "status": "UNAUTHORIZED",
+    }));
+
+    warp::reply::with_status(response, StatusCode::UNAUTHORIZED)
+}
+
+pub async fn create_room_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+>(
+    controller: UsersController<D, H, T, I>,
+    rooms_controller: RoomsController<GD, GSN>,
+    token: String,
+    request: CreateRoomRequest,
+) -> WarpResult<impl Reply> {
+    let user_id = match controller.authorize(token).await {
+        Ok(user_id) => user_id,
+        Err(_) => return Ok(unauthorized_reply()),
+    };
+
+    match rooms_controller.create_room(user_id, request).await {
+        Ok(code) => {
+            let response = warp::reply::json(&serde_json::json!({
+                "status": "OK",
+                "code": code
+            }));
+            Ok(warp::reply::with_status(response, StatusCode::CREATED))
+        }
+        Err(err) => Ok(room_error_reply(err)),
+    }
+}
+
+pub async fn room_info_handler<
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+>(
+    code: String,
+    rooms_controller: RoomsController<GD, GSN>,
+) -> WarpResult<impl Reply> {
+    match rooms_controller.room_info(&code).await {
+        Ok(info) => {
+            let response = warp::reply::json(&serde_json::json!({
+                "status": "OK",
+                "code": info.code,
+                "pack": info.pack,
+                "players": info.players,
+            }));
+            Ok(warp::reply::with_status(response, StatusCode::OK))
+        }
+        Err(err) => Ok(room_error_reply(err)),
+    }
+}
+
+pub async fn start_room_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+>(
+    code: String,
+    controller: UsersController<D, H, T, I>,
+    rooms_controller: RoomsController<GD, GSN>,
+    token: String,
+) -> WarpResult<impl Reply> {
+    let user_id = match controller.authorize(token).await {
+        Ok(user_id) => user_id,
+        Err(_) => return Ok(unauthorized_reply()),
+    };
+
+    match rooms_controller.start_game(&code, &user_id).await {
+        Ok(()) => {
+            let response = warp::reply::json(&serde_json::json!({
+                "status": "OK",
+            }));
+            Ok(warp::reply::with_status(response, StatusCode::OK))
+        }
+        Err(err) => Ok(room_error_reply(err)),
+    }
+}
+
+pub async fn room_websocket_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+>(
+    code: String,
+    token: String,
+    controller: UsersController<D, H, T, I>,
+    rooms_controller: RoomsController<GD, GSN>,
+    ws: Ws,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let authorized = controller.authorize(token).await;
+    let id = match authorized {
+        Ok(id) => id,
+        Err(_) => {
+            log::error!("Unauthenticated user");
+            return Err(warp::reject::not_found());
+        }
+    };
+
+    match rooms_controller.join(&code).await {
+        Ok(game_controller) => {
+            if controller.is_admin(&id) {
+                game_controller.add_moderator(&id).await;
+            }
+            Ok(ws.on_upgrade(move |socket| game_controller.start(id, socket)))
+        }
+        Err(e) => {
+            log::error!("Failed to join room {code}: {e}");
+            Err(warp::reject::not_found())
+        }
+    }
+}
+
+pub async fn duel_websocket_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+>(
+    token: String,
+    controller: UsersController<D, H, T, I>,
+    duels_controller: DuelsController<GD, GSN>,
+    ws: Ws,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let authorized = controller.authorize(token).await;
+    let id = match authorized {
+        Ok(id) => id,
+        Err(_) => {
+            log::error!("Unauthenticated user");
+            return Err(warp::reject::not_found());
+        }
+    };
+
+    Ok(ws.on_upgrade(move |socket| duels_controller.start(id, socket)))
+}
+
+pub async fn practice_websocket_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    QS: QuestionStore + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+>(
+    category: String,
+    token: String,
+    controller: UsersController<D, H, T, I>,
+    practice_controller: PracticeController<GD, QS, GSN>,
+    ws: Ws,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let authorized = controller.authorize(token).await;
+    let id = match authorized {
+        Ok(id) => id,
+        Err(_) => {
+            log::error!("Unauthenticated user");
+            return Err(warp::reject::not_found());
+        }
+    };
+
+    match practice_controller
+        .create_session(id.clone(), &category)
+        .await
+    {
+        Ok(game_controller) => Ok(ws.on_upgrade(move |socket| game_controller.start(id, socket))),
+        Err(e) => {
+            log::error!("Failed to start practice in {category}: {e}");
+            Err(warp::reject::not_found())
+        }
+    }
+}
+
+pub async fn spectate_handler<
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    JS: JobSchedular + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Send + Sync + Clone + 'static,
+>(
+    game_controller: GameController<GD, JS, GSN>,
+    ws: Ws,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(ws.on_upgrade(move |socket| game_controller.spectate(socket)))
+}
+
+pub async fn room_spectate_handler<
+    GD: GameDatabase + Send + Sync + Clone + 'static,
+    GSN: GameStartNotifier + Default + Send + Sync + Clone + 'static,
+>(
+    code: String,
+    rooms_controller: RoomsController<GD, GSN>,
+    ws: Ws,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match rooms_controller.join(&code).await {
+        Ok(game_controller) => Ok(ws.on_upgrade(move |socket| game_controller.spectate(socket))),
+        Err(e) => {
+            log::error!("Failed to spectate room {code}: {e}");
+            Err(warp::reject::not_found())
+        }
+    }
+}
+
+fn forbidden_reply() -> warp::reply::WithStatus<warp::reply::Json> {
+    let response = warp::reply::json(&serde_json::json!({
+        "status": "FORBIDDEN",
+    }));
+
+    warp::reply::with_status(response, StatusCode::FORBIDDEN)
+}
+
+/// The id of the admin the token belongs to, or the reply for anyone else.
+async fn authorize_admin<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+>(
+    controller: &UsersController<D, H, T, I>,
+    token: String,
+) -> Result<String, warp::reply::WithStatus<warp::reply::Json>> {
+    let id = controller
+        .authorize(token)
+        .await
+        .or(Err(unauthorized_reply()))?;
+
+    if !controller.is_admin(&id) {
+        return Err(forbidden_reply());
+    }
+
+    Ok(id)
+}
+
+fn question_bank_error_reply(err: QuestionBankError) -> warp::reply::WithStatus<warp::reply::Json> {
+    let status = match err {
+        QuestionBankError::QuestionNotFound => StatusCode::NOT_FOUND,
+        QuestionBankError::RequestValidationError(_) => StatusCode::BAD_REQUEST,
+        QuestionBankError::PackError(_) => StatusCode::BAD_REQUEST,
+        QuestionBankError::DatabaseError => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    let response = warp::reply::json(&serde_json::json!({
+        "status": "ERROR",
+        "message": err.to_string(),
+    }));
+
+    warp::reply::with_status(response, status)
+}
+
+fn question_bank_reply<S: serde::Serialize>(
+    key: &str,
+    value: &S,
+    status: StatusCode,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    let response = warp::reply::json(&serde_json::json!({
+        "status": "OK",
+        key: value,
+    }));
+
+    warp::reply::with_status(response, status)
+}
+
+pub async fn create_question_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+    QS: QuestionStore + Send + Sync + Clone + 'static,
+    QI: IDGenerator + Send + Sync + Clone + 'static,
+>(
+    controller: UsersController<D, H, T, I>,
+    bank_controller: QuestionBankController<QS, QI>,
+    token: String,
+    request: QuestionRequest,
+) -> WarpResult<impl Reply> {
+    if let Err(reply) = authorize_admin(&controller, token).await {
+        return Ok(reply);
+    }
+
+    match bank_controller.create(request).await {
+        Ok(question) => Ok(question_bank_reply(
+            "question",
+            &question,
+            StatusCode::CREATED,
+        )),
+        Err(err) => Ok(question_bank_error_reply(err)),
+    }
+}
+
+pub async fn update_question_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+    QS: QuestionStore + Send + Sync + Clone + 'static,
+    QI: IDGenerator + Send + Sync + Clone + 'static,
+>(
+    id: String,
+    controller: UsersController<D, H, T, I>,
+    bank_controller: QuestionBankController<QS, QI>,
+    token: String,
+    request: QuestionRequest,
+) -> WarpResult<impl Reply> {
+    if let Err(reply) = authorize_admin(&controller, token).await {
+        return Ok(reply);
+    }
+
+    match bank_controller.update(&id, request).await {
+        Ok(question) => Ok(question_bank_reply("question", &question, StatusCode::OK)),
+        Err(err) => Ok(question_bank_error_reply(err)),
+    }
+}
+
+pub async fn search_questions_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+    QS: QuestionStore + Send + Sync + Clone + 'static,
+    QI: IDGenerator + Send + Sync + Clone + 'static,
+>(
+    controller: UsersController<D, H, T, I>,
+    bank_controller: QuestionBankController<QS, QI>,
+    token: String,
+    search: QuestionSearchRequest,
+) -> WarpResult<impl Reply> {
+    if let Err(reply) = authorize_admin(&controller, token).await {
+        return Ok(reply);
+    }
+
+    match bank_controller.search(&search).await {
+        Ok(questions) => Ok(question_bank_reply("questions", &questions, StatusCode::OK)),
+        Err(err) => Ok(question_bank_error_reply(err)),
+    }
+}
+
+/// Retires the question rather than deleting it, so past games keep it.
+pub async fn retire_question_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+    QS: QuestionStore + Send + Sync + Clone + 'static,
+    QI: IDGenerator + Send + Sync + Clone + 'static,
+>(
+    id: String,
+    controller: UsersController<D, H, T, I>,
+    bank_controller: QuestionBankController<QS, QI>,
+    token: String,
+) -> WarpResult<impl Reply> {
+    if let Err(reply) = authorize_admin(&controller, token).await {
+        return Ok(reply);
+    }
+
+    match bank_controller.retire(&id).await {
+        Ok(question) => Ok(question_bank_reply("question", &question, StatusCode::OK)),
+        Err(err) => Ok(question_bank_error_reply(err)),
+    }
+}
+
+/// Imports a question pack sent as the request body. The reply is the
+/// import report, which lists every row that has to be fixed.
+pub async fn import_questions_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+    QS: QuestionStore + Send + Sync + Clone + 'static,
+    QI: IDGenerator + Send + Sync + Clone + 'static,
+>(
+    controller: UsersController<D, H, T, I>,
+    bank_controller: QuestionBankController<QS, QI>,
+    token: String,
+    request: QuestionPackRequest,
+    body: warp::hyper::body::Bytes,
+) -> WarpResult<impl Reply> {
+    if let Err(reply) = authorize_admin(&controller, token).await {
+        return Ok(reply);
+    }
+
+    let Ok(source) = std::str::from_utf8(&body) else {
+        let response = warp::reply::json(&serde_json::json!({
+            "status": "ERROR",
+            "message": "pack is not valid UTF-8",
+        }));
+        return Ok(warp::reply::with_status(response, StatusCode::BAD_REQUEST));
+    };
+
+    let report = match bank_controller
+        .import(source, request.format, request.dry_run)
+        .await
+    {
+        Ok(report) => report,
+        Err(err) => return Ok(question_bank_error_reply(err)),
+    };
+
+    if !report.errors.is_empty() {
+        let response = warp::reply::json(&serde_json::json!({
+            "status": "ERROR",
+            "report": report,
+        }));
+        return Ok(warp::reply::with_status(
+            response,
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ));
+    }
+
+    let status = if report.imported {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok(question_bank_reply("report", &report, status))
+}
+
+pub async fn export_questions_handler<
+    D: UsersDatabase + Clone,
+    H: Hasher + Clone,
+    T: TokenGenerator + Clone,
+    I: IDGenerator + Clone,
+    QS: QuestionStore + Send + Sync + Clone + 'static,
+    QI: IDGenerator + Send + Sync + Clone + 'static,
+>(
+    controller: UsersController<D, H, T, I>,
+    bank_controller: QuestionBankController<QS, QI>,
+    token: String,
+    request: QuestionPackRequest,
+    search: QuestionSearchRequest,
+) -> WarpResult<warp::reply::Response> {
+    if let Err(reply) = authorize_admin(&controller, token).await {
+        return Ok(reply.into_response());
+    }
+
+    match bank_controller.export(&search, request.format).await {
+        Ok(pack) => {
+            Ok(
+                warp::reply::with_header(pack, "content-type", request.format.content_type())
+                    .into_response(),
+            )
+        }
+        Err(err) => Ok(question_bank_error_reply(err).into_response()),
+    }
+}
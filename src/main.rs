@@ -1,38 +1,108 @@
+mod cli;
+
 use segon::{
     adapters::{
-        GameMemoryDatabase, Jwt, Notifier, Schedular, ShaHasher, UsersMemoryDatabase, UuidGenerator,
+        GameMemoryDatabase, Jwt, Notifier, QuestionMemoryStore, Schedular, ShaHasher,
+        UsersMemoryDatabase, UuidGenerator,
+    },
+    controllers::{
+        ChatFilter, Composition, DuelsController, GameComposer, GameController, PracticeController,
+        QuestionBankController, RoomsController, SseSessions, UsersController,
     },
-    controllers::{GameController, UsersController},
     handlers::{
-        login_handler, register_handler, websocket_handler, with_game_controller, with_json_body,
+        create_question_handler, create_room_handler, duel_websocket_handler,
+        export_questions_handler, import_questions_handler, login_handler,
+        practice_websocket_handler, register_handler, retire_question_handler, room_info_handler,
+        room_spectate_handler, room_websocket_handler, search_questions_handler, spectate_handler,
+        sse_handler, sse_message_handler, start_room_handler, update_question_handler,
+        websocket_handler, with_auth, with_bearer_token, with_duels_controller,
+        with_game_controller, with_json_body, with_practice_controller,
+        with_question_bank_controller, with_rooms_controller, with_sse_sessions,
         with_users_controller,
     },
-    request::{LoginRequest, RegisterRequest},
+    models::ClientMessage,
+    request::{
+        CreateRoomRequest, LoginRequest, QuestionPackRequest, QuestionRequest,
+        QuestionSearchRequest, RegisterRequest,
+    },
 };
 use std::convert::Infallible;
 use warp::{hyper::StatusCode, Filter, Rejection, Reply};
 
+const MEDIA_DIRECTORY: &str = "media";
+const MEDIA_CACHE_CONTROL: &str = "public, max-age=86400";
+const MAX_PACK_SIZE: u64 = 4 * 1024 * 1024;
+const CHAT_FILTER_FILE: &str = "chat_filter.txt";
+// comma separated user ids, the subject of each admin's token
+const ADMINS_VAR: &str = "SEGON_ADMINS";
+// "true" or "1" to let only logged in users spectate
+const SPECTATE_AUTH_VAR: &str = "SEGON_SPECTATE_AUTH";
+
 #[tokio::main]
 async fn main() {
     pretty_env_logger::init();
 
+    // segon questions import|export ...
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|command| command == "questions") {
+        std::process::exit(cli::run(&args[1..]).await);
+    }
+
     let cors = warp::cors()
         .allow_any_origin()
-        .allow_methods(vec!["GET", "POST", "DELETE"])
-        .allow_headers(vec!["Content-Length", "Content-Type"]);
+        .allow_methods(vec!["GET", "POST", "PUT", "DELETE"])
+        .allow_headers(vec!["Content-Length", "Content-Type", "Authorization"]);
 
     // init db
     // let db = RedisUsersDatabase::new().await.unwrap();
 
     // init users controller
+    let admins = std::env::var(ADMINS_VAR)
+        .map(|admins| admins.split(',').map(|admin| admin.trim().into()).collect())
+        .unwrap_or_default();
     let users_controller: UsersController<UsersMemoryDatabase, ShaHasher, Jwt, UuidGenerator> =
-        UsersController::new(UsersMemoryDatabase::new());
+        UsersController::new(UsersMemoryDatabase::new()).with_admins(admins);
+
+    // chat filter, one word per line
+    let chat_filter = std::fs::read_to_string(CHAT_FILTER_FILE)
+        .map(|list| ChatFilter::parse(&list))
+        .unwrap_or_default();
+
+    // each scheduled game is composed from the question bank, the sample
+    // game is played until the bank has enough questions
+    let question_store = QuestionMemoryStore::default();
+    let game_db = GameMemoryDatabase::default();
+    let composer = GameComposer::new(
+        question_store.clone(),
+        game_db.clone(),
+        Composition::default(),
+    );
 
     // init game controller
     let notifier = Notifier::new();
-    let schedular = Schedular::new(notifier.clone()).await.unwrap();
-    let game_controller =
-        GameController::new(GameMemoryDatabase::default(), schedular.clone(), notifier);
+    let schedular = Schedular::with_preparer(notifier.clone(), composer.clone())
+        .await
+        .unwrap();
+    let game_controller = GameController::new(game_db.clone(), schedular.clone(), notifier)
+        .with_chat_filter(chat_filter.clone());
+    tokio::spawn(composer.record_asked(game_controller.game_starts()));
+
+    // init rooms controller
+    let rooms_controller: RoomsController<GameMemoryDatabase, Notifier> =
+        RoomsController::new(game_db.clone()).with_chat_filter(chat_filter);
+    tokio::spawn(rooms_controller.clone().prune_periodically());
+
+    // init duels controller
+    let duels_controller: DuelsController<GameMemoryDatabase, Notifier> =
+        DuelsController::new(game_db.clone());
+
+    // init practice controller
+    let practice_controller: PracticeController<GameMemoryDatabase, QuestionMemoryStore, Notifier> =
+        PracticeController::new(game_db, question_store.clone());
+
+    // init question bank controller
+    let question_bank_controller: QuestionBankController<QuestionMemoryStore, UuidGenerator> =
+        QuestionBankController::new(question_store);
 
     // POST /register
     let register_route = warp::path("register")
@@ -50,21 +120,210 @@ async fn main() {
         .and_then(login_handler)
         .map(|ok| ok);
 
+    // POST /rooms
+    let create_room_route = warp::path!("rooms")
+        .and(warp::post())
+        .and(with_users_controller(users_controller.clone()))
+        .and(with_rooms_controller(rooms_controller.clone()))
+        .and(with_bearer_token())
+        .and(with_json_body::<CreateRoomRequest>())
+        .and_then(create_room_handler)
+        .map(|ok| ok);
+
+    // GET /rooms/{code}
+    let room_info_route = warp::path!("rooms" / String)
+        .and(warp::get())
+        .and(with_rooms_controller(rooms_controller.clone()))
+        .and_then(room_info_handler)
+        .map(|ok| ok);
+
+    // POST /rooms/{code}/start
+    let start_room_route = warp::path!("rooms" / String / "start")
+        .and(warp::post())
+        .and(with_users_controller(users_controller.clone()))
+        .and(with_rooms_controller(rooms_controller.clone()))
+        .and(with_bearer_token())
+        .and_then(start_room_handler)
+        .map(|ok| ok);
+
+    // GET /rooms/{code}/game/{token} -> websocket upgrade
+    let room_game_route = warp::path!("rooms" / String / "game" / String)
+        .and(with_users_controller(users_controller.clone()))
+        .and(with_rooms_controller(rooms_controller.clone()))
+        .and(warp::ws())
+        .and_then(room_websocket_handler)
+        .map(|ok| ok);
+
+    // GET /duel/{token} -> websocket upgrade, queues for a duel
+    let duel_route = warp::path!("duel" / String)
+        .and(with_users_controller(users_controller.clone()))
+        .and(with_duels_controller(duels_controller))
+        .and(warp::ws())
+        .and_then(duel_websocket_handler)
+        .map(|ok| ok);
+
+    // GET /practice/{category}/{token} -> websocket upgrade, solo session on the
+    // bank's questions in the category, or on the pack of that name
+    let practice_route = warp::path!("practice" / String / String)
+        .and(with_users_controller(users_controller.clone()))
+        .and(with_practice_controller(practice_controller))
+        .and(warp::ws())
+        .and_then(practice_websocket_handler)
+        .map(|ok| ok);
+
+    // GET /rooms/{code}/spectate?token= -> websocket upgrade, the token is only
+    // needed when spectating requires a login
+    let spectate_auth = std::env::var(SPECTATE_AUTH_VAR)
+        .is_ok_and(|required| matches!(required.trim(), "true" | "1"));
+    let room_spectate_route = warp::path!("rooms" / String / "spectate")
+        .and(with_auth(users_controller.clone(), spectate_auth))
+        .and(with_rooms_controller(rooms_controller))
+        .and(warp::ws())
+        .and_then(room_spectate_handler)
+        .map(|ok| ok);
+
+    // GET /spectate?token= -> websocket upgrade, the token is only needed when
+    // spectating requires a login
+    let spectate_route = warp::path!("spectate")
+        .and(with_auth(users_controller.clone(), spectate_auth))
+        .and(with_game_controller(game_controller.clone()))
+        .and(warp::ws())
+        .and_then(spectate_handler)
+        .map(|ok| ok);
+
+    // GET /game/events/{token} -> server-sent events, websocket fallback
+    let sse_sessions = SseSessions::default();
+    let sse_route = warp::path!("game" / "events" / String)
+        .and(warp::get())
+        .and(with_users_controller(users_controller.clone()))
+        .and(with_game_controller(game_controller.clone()))
+        .and(with_sse_sessions(sse_sessions.clone()))
+        .and_then(sse_handler)
+        .map(|ok| ok);
+
+    // POST /game/answer -> client message for the event stream's session
+    let sse_message_route = warp::path!("game" / "answer")
+        .and(warp::post())
+        .and(with_users_controller(users_controller.clone()))
+        .and(with_sse_sessions(sse_sessions))
+        .and(with_bearer_token())
+        .and(with_json_body::<ClientMessage>())
+        .and_then(sse_message_handler)
+        .map(|ok| ok);
+
     // GET /game -> websocket upgrade
     let chat = warp::path("game")
-        .and(with_users_controller(users_controller))
+        .and(with_users_controller(users_controller.clone()))
         .and(with_game_controller(game_controller))
         .and(warp::ws())
         .and(warp::path::param())
         .and_then(websocket_handler)
         .map(|ok| ok);
 
+    // POST /questions -> admin only
+    let create_question_route = warp::path!("questions")
+        .and(warp::post())
+        .and(with_users_controller(users_controller.clone()))
+        .and(with_question_bank_controller(
+            question_bank_controller.clone(),
+        ))
+        .and(with_bearer_token())
+        .and(with_json_body::<QuestionRequest>())
+        .and_then(create_question_handler)
+        .map(|ok| ok);
+
+    // POST /questions/import?format=&dry_run= -> admin only, the pack is the body
+    let import_questions_route = warp::path!("questions" / "import")
+        .and(warp::post())
+        .and(with_users_controller(users_controller.clone()))
+        .and(with_question_bank_controller(
+            question_bank_controller.clone(),
+        ))
+        .and(with_bearer_token())
+        .and(warp::query::<QuestionPackRequest>())
+        .and(warp::body::content_length_limit(MAX_PACK_SIZE))
+        .and(warp::body::bytes())
+        .and_then(import_questions_handler)
+        .map(|ok| ok);
+
+    // GET /questions/export?format=&category=&tag=&difficulty=&text=&include_retired= -> admin only
+    let export_questions_route = warp::path!("questions" / "export")
+        .and(warp::get())
+        .and(with_users_controller(users_controller.clone()))
+        .and(with_question_bank_controller(
+            question_bank_controller.clone(),
+        ))
+        .and(with_bearer_token())
+        .and(warp::query::<QuestionPackRequest>())
+        .and(warp::query::<QuestionSearchRequest>())
+        .and_then(export_questions_handler)
+        .map(|ok| ok);
+
+    // GET /questions?category=&tag=&difficulty=&text=&include_retired= -> admin only
+    let search_questions_route = warp::path!("questions")
+        .and(warp::get())
+        .and(with_users_controller(users_controller.clone()))
+        .and(with_question_bank_controller(
+            question_bank_controller.clone(),
+        ))
+        .and(with_bearer_token())
+        .and(warp::query::<QuestionSearchRequest>())
+        .and_then(search_questions_handler)
+        .map(|ok| ok);
+
+    // PUT /questions/{id} -> admin only
+    let update_question_route = warp::path!("questions" / String)
+        .and(warp::put())
+        .and(with_users_controller(users_controller.clone()))
+        .and(with_question_bank_controller(
+            question_bank_controller.clone(),
+        ))
+        .and(with_bearer_token())
+        .and(with_json_body::<QuestionRequest>())
+        .and_then(update_question_handler)
+        .map(|ok| ok);
+
+    // DELETE /questions/{id} -> admin only, retires the question
+    let retire_question_route = warp::path!("questions" / String)
+        .and(warp::delete())
+        .and(with_users_controller(users_controller))
+        .and(with_question_bank_controller(question_bank_controller))
+        .and(with_bearer_token())
+        .and_then(retire_question_handler)
+        .map(|ok| ok);
+
+    // GET /media/{path} -> question attachments
+    let media_route = warp::path("media")
+        .and(warp::get())
+        .and(warp::fs::dir(MEDIA_DIRECTORY))
+        .with(warp::reply::with::header(
+            "cache-control",
+            MEDIA_CACHE_CONTROL,
+        ));
+
     // Serve build directory
     // let serve = warp::fs::dir("client/build");
 
     let routes = register_route
         .or(login_route)
+        .or(sse_route)
+        .or(sse_message_route)
         .or(chat)
+        .or(create_room_route)
+        .or(room_info_route)
+        .or(start_room_route)
+        .or(room_game_route)
+        .or(room_spectate_route)
+        .or(spectate_route)
+        .or(duel_route)
+        .or(practice_route)
+        .or(create_question_route)
+        .or(import_questions_route)
+        .or(export_questions_route)
+        .or(search_questions_route)
+        .or(update_question_route)
+        .or(retire_question_route)
+        .or(media_route)
         // .or(serve)
         .recover(handle_rejection)
         .with(cors);
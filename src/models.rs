@@ -1,15 +1,340 @@
+use rand::{seq::SliceRandom, Rng};
 use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+use thiserror::Error;
 
-#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub const MIN_OPTIONS: usize = 2;
+pub const MAX_OPTIONS: usize = 6;
+pub const DEFAULT_MAX_DISTANCE: usize = 2;
+/// Points for a correct answer. Estimates that are close earn part of them,
+/// so scores count tenths of a correct answer. Scores kept from before
+/// estimates counted whole correct answers and have to be multiplied by
+/// this to compare.
+pub const MAX_POINTS: u32 = 10;
+pub const LIFELINES_PER_GAME: u32 = 1;
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Game {
     pub questions: Vec<Question>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+impl Game {
+    pub fn validate(&self) -> Result<(), QuestionValidationError> {
+        self.questions.iter().try_for_each(Question::validate)
+    }
+
+    /// A game of up to `count` questions picked at random, in random order.
+    pub fn sample<R: Rng>(&self, rng: &mut R, count: usize) -> Game {
+        let mut questions: Vec<_> = self
+            .questions
+            .choose_multiple(rng, count)
+            .cloned()
+            .collect();
+        questions.shuffle(rng);
+        Game { questions }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Question {
     pub question: String,
-    pub options: [String; 4],
-    pub answer_idx: OptionIndex,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media: Option<Media>,
+    #[serde(flatten)]
+    pub kind: QuestionKind,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+/// A question kept in the question bank, with what is needed to find it and
+/// pick it for a game. Retired questions stay in the bank but are no longer
+/// used.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct BankQuestion {
+    pub id: String,
+    #[serde(flatten)]
+    pub question: Question,
+    pub category: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub difficulty: Difficulty,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub retired: bool,
+    /// When the question was last picked for a game, in Unix milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_asked: Option<u64>,
+}
+
+/// How a question pack is written. JSON and YAML packs have the shape of a
+/// `Game`, with the bank fields next to each question, which JSON packs can
+/// also set once for all of them. CSV packs have one row per question and do
+/// not carry media.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PackFormat {
+    #[default]
+    Json,
+    Csv,
+    Yaml,
+}
+
+impl PackFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "json" => Some(PackFormat::Json),
+            "csv" => Some(PackFormat::Csv),
+            "yaml" | "yml" => Some(PackFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(Self::from_name)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            PackFormat::Json => "application/json",
+            PackFormat::Csv => "text/csv",
+            PackFormat::Yaml => "application/yaml",
+        }
+    }
+}
+
+/// A file served from the media directory under `/media/{path}`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct Media {
+    pub kind: MediaKind,
+    pub path: String,
+    pub alt: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Image,
+    Audio,
+}
+
+impl Media {
+    pub fn validate(&self) -> Result<(), QuestionValidationError> {
+        let path = std::path::Path::new(&self.path);
+        let is_plain = path
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)));
+
+        if self.path.is_empty() || !is_plain {
+            return Err(QuestionValidationError::InvalidMediaPath);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum QuestionKind {
+    Choice {
+        options: Vec<String>,
+        answer_idx: OptionIndex,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        option_media: Vec<Option<Media>>,
+    },
+    Text {
+        accepted_answers: Vec<String>,
+        #[serde(default = "default_max_distance")]
+        max_distance: usize,
+    },
+    Number {
+        answer: f64,
+        tolerance: f64,
+    },
+}
+
+fn default_max_distance() -> usize {
+    DEFAULT_MAX_DISTANCE
+}
+
+impl Question {
+    pub fn choice(question: String, options: Vec<String>, answer_idx: OptionIndex) -> Self {
+        Self {
+            question,
+            media: None,
+            kind: QuestionKind::Choice {
+                options,
+                answer_idx,
+                option_media: Vec::new(),
+            },
+        }
+    }
+
+    pub fn true_false(question: String, answer: bool) -> Self {
+        let answer_idx = if answer {
+            OptionIndex::One
+        } else {
+            OptionIndex::Two
+        };
+
+        Self::choice(question, vec!["True".into(), "False".into()], answer_idx)
+    }
+
+    pub fn text(question: String, accepted_answers: Vec<String>) -> Self {
+        Self {
+            question,
+            media: None,
+            kind: QuestionKind::Text {
+                accepted_answers,
+                max_distance: DEFAULT_MAX_DISTANCE,
+            },
+        }
+    }
+
+    pub fn number(question: String, answer: f64, tolerance: f64) -> Self {
+        Self {
+            question,
+            media: None,
+            kind: QuestionKind::Number { answer, tolerance },
+        }
+    }
+
+    pub fn options(&self) -> &[String] {
+        match &self.kind {
+            QuestionKind::Choice { options, .. } => options,
+            QuestionKind::Text { .. } | QuestionKind::Number { .. } => &[],
+        }
+    }
+
+    pub fn with_media(mut self, media: Media) -> Self {
+        self.media = Some(media);
+        self
+    }
+
+    pub fn option_media(&self) -> &[Option<Media>] {
+        match &self.kind {
+            QuestionKind::Choice { option_media, .. } => option_media,
+            QuestionKind::Text { .. } | QuestionKind::Number { .. } => &[],
+        }
+    }
+
+    /// Every attachment of the question, for clients to preload.
+    pub fn all_media(&self) -> Vec<Media> {
+        self.media
+            .iter()
+            .chain(self.option_media().iter().flatten())
+            .cloned()
+            .collect()
+    }
+
+    /// The answer that is revealed as correct.
+    pub fn answer(&self) -> Answer {
+        match &self.kind {
+            QuestionKind::Choice { answer_idx, .. } => Answer::Choice(answer_idx.clone()),
+            QuestionKind::Text {
+                accepted_answers, ..
+            } => Answer::Text(accepted_answers.first().cloned().unwrap_or_default()),
+            QuestionKind::Number { answer, .. } => Answer::Number(*answer),
+        }
+    }
+
+    pub fn answer_kind(&self) -> AnswerKind {
+        match self.kind {
+            QuestionKind::Choice { .. } => AnswerKind::Choice,
+            QuestionKind::Text { .. } => AnswerKind::Text,
+            QuestionKind::Number { .. } => AnswerKind::Number,
+        }
+    }
+
+    pub fn has_option(&self, idx: &OptionIndex) -> bool {
+        idx.index() < self.options().len()
+    }
+
+    pub fn accepts(&self, answer: &Answer) -> bool {
+        match (&self.kind, answer) {
+            (QuestionKind::Choice { .. }, Answer::Choice(idx)) => self.has_option(idx),
+            (QuestionKind::Text { .. }, Answer::Text(_)) => true,
+            (QuestionKind::Number { .. }, Answer::Number(answer)) => answer.is_finite(),
+            _ => false,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), QuestionValidationError> {
+        use QuestionValidationError::*;
+
+        self.all_media().iter().try_for_each(Media::validate)?;
+
+        match &self.kind {
+            QuestionKind::Choice {
+                options,
+                answer_idx,
+                option_media,
+            } => {
+                if options.len() < MIN_OPTIONS {
+                    return Err(TooFewOptions);
+                }
+
+                if options.len() > MAX_OPTIONS {
+                    return Err(TooManyOptions);
+                }
+
+                if answer_idx.index() >= options.len() {
+                    return Err(AnswerOutOfRange);
+                }
+
+                if option_media.len() > options.len() {
+                    return Err(TooManyOptionMedia);
+                }
+            }
+            QuestionKind::Text {
+                accepted_answers, ..
+            } => {
+                if accepted_answers
+                    .iter()
+                    .all(|answer| answer.trim().is_empty())
+                {
+                    return Err(NoAcceptedAnswers);
+                }
+            }
+            QuestionKind::Number { answer, tolerance } => {
+                if !answer.is_finite() {
+                    return Err(InvalidNumber);
+                }
+
+                if !tolerance.is_finite() || *tolerance <= 0.0 {
+                    return Err(InvalidTolerance);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum QuestionValidationError {
+    #[error("question has less than {MIN_OPTIONS} options")]
+    TooFewOptions,
+    #[error("question has more than {MAX_OPTIONS} options")]
+    TooManyOptions,
+    #[error("answer index is out of range")]
+    AnswerOutOfRange,
+    #[error("question has no accepted answers")]
+    NoAcceptedAnswers,
+    #[error("numeric answer is not a finite number")]
+    InvalidNumber,
+    #[error("tolerance must be a positive number")]
+    InvalidTolerance,
+    #[error("option media has more entries than options")]
+    TooManyOptionMedia,
+    #[error("media path must be relative to the media directory")]
+    InvalidMediaPath,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
@@ -18,41 +343,600 @@ pub enum OptionIndex {
     Two,
     Three,
     Four,
+    Five,
+    Six,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl OptionIndex {
+    pub fn index(&self) -> usize {
+        match self {
+            OptionIndex::One => 0,
+            OptionIndex::Two => 1,
+            OptionIndex::Three => 2,
+            OptionIndex::Four => 3,
+            OptionIndex::Five => 4,
+            OptionIndex::Six => 5,
+        }
+    }
+
+    pub fn from_index(idx: usize) -> Option<Self> {
+        match idx {
+            0 => Some(OptionIndex::One),
+            1 => Some(OptionIndex::Two),
+            2 => Some(OptionIndex::Three),
+            3 => Some(OptionIndex::Four),
+            4 => Some(OptionIndex::Five),
+            5 => Some(OptionIndex::Six),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub enum Answer {
+    Choice(OptionIndex),
+    Text(String),
+    Number(f64),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum AnswerKind {
+    Choice,
+    Text,
+    Number,
+}
+
+/// What a client is shown of a question, without its answer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QuestionView {
+    pub question: String,
+    pub options: Vec<String>,
+    pub kind: AnswerKind,
+    pub media: Option<Media>,
+    pub option_media: Vec<Option<Media>>,
+}
+
+impl From<&Question> for QuestionView {
+    fn from(question: &Question) -> Self {
+        Self {
+            question: question.question.clone(),
+            options: question.options().to_vec(),
+            kind: question.answer_kind(),
+            media: question.media.clone(),
+            option_media: question.option_media().to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum ServerMessage {
     TimeTillGame {
         time: u64,
+        sent_at: u64,
+        deadline: u64,
     },
+    WaitingForHost,
     Question {
-        question: String,
-        options: [String; 4],
+        #[serde(flatten)]
+        question: QuestionView,
+        sent_at: u64,
+        deadline: u64,
+    },
+    Preload {
+        media: Vec<Media>,
     },
     Answer {
         status: AnswerStatus,
         answer_idx: OptionIndex,
+        sent_at: u64,
+        deadline: u64,
+    },
+    TextAnswer {
+        status: AnswerStatus,
+        answer: String,
+        sent_at: u64,
+        deadline: u64,
+    },
+    NumberAnswer {
+        status: AnswerStatus,
+        answer: f64,
+        error: Option<f64>,
+        sent_at: u64,
+        deadline: u64,
+    },
+    QuestionStats {
+        counts: Vec<u32>,
+        answered: u32,
+        correct: u32,
+        players: u32,
+        sent_at: u64,
+        deadline: u64,
     },
     NoGame,
     GameEnd {
         score: u32,
     },
-    GameStart,
+    GameStart {
+        sent_at: u64,
+        deadline: u64,
+    },
+    Resume {
+        question_index: usize,
+        question: Option<QuestionView>,
+        revealing: bool,
+        time_remaining: u64,
+        answer: Option<Answer>,
+        answers: Vec<AnswerStatus>,
+        score: u32,
+        sent_at: u64,
+        deadline: u64,
+    },
+    LifelineUsed {
+        lifeline: Lifeline,
+        remaining: u32,
+    },
+    HiddenOptions {
+        hidden: Vec<OptionIndex>,
+    },
+    SecondChance,
+    Team {
+        name: Option<String>,
+        members: Vec<String>,
+    },
+    TeammateAnswer {
+        user_id: String,
+        answer: Answer,
+    },
+    TeamLeaderboard {
+        teams: Vec<TeamScore>,
+    },
+    DuelFound {
+        opponent: String,
+        rating: u32,
+        opponent_rating: u32,
+    },
+    DuelResult {
+        outcome: DuelOutcome,
+        forfeit: bool,
+        score: u32,
+        opponent_score: u32,
+        rating: u32,
+        rating_change: i32,
+    },
+    PracticeStats {
+        stats: PracticeStats,
+    },
+    Chat {
+        id: u64,
+        user_id: String,
+        text: String,
+    },
+    ChatDeleted {
+        id: u64,
+    },
+    ChatMuted {
+        user_id: String,
+    },
+    Reactions {
+        counts: BTreeMap<String, u32>,
+    },
+    Reveal {
+        answer: Answer,
+        sent_at: u64,
+        deadline: u64,
+    },
+    Welcome {
+        protocol_version: u32,
+        capabilities: Vec<String>,
+        encoding: Encoding,
+    },
+    /// Answers a `Ping`. With the time the client got it back, the server
+    /// clock is ahead of the client's by
+    /// `((received_at - client_time) + (sent_at - client_now)) / 2`, assuming
+    /// the network takes as long each way. Clients add that offset to their
+    /// own clock before comparing it against deadlines.
+    Pong {
+        client_time: u64,
+        received_at: u64,
+        sent_at: u64,
+    },
     Error {
+        code: ErrorCode,
         message: String,
     },
 }
 
+impl ServerMessage {
+    /// Records when the message leaves the server on the messages that
+    /// carry a send time. Timestamps are milliseconds since the Unix epoch.
+    pub fn stamp(&mut self, now: u64) {
+        match self {
+            ServerMessage::TimeTillGame { sent_at, .. }
+            | ServerMessage::Question { sent_at, .. }
+            | ServerMessage::GameStart { sent_at, .. }
+            | ServerMessage::Resume { sent_at, .. }
+            | ServerMessage::Reveal { sent_at, .. }
+            | ServerMessage::Answer { sent_at, .. }
+            | ServerMessage::TextAnswer { sent_at, .. }
+            | ServerMessage::NumberAnswer { sent_at, .. }
+            | ServerMessage::QuestionStats { sent_at, .. }
+            | ServerMessage::Pong { sent_at, .. } => *sent_at = now,
+            _ => (),
+        }
+    }
+
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        ServerMessage::Error {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// What an `Error` message is about, so clients do not have to match on the
+/// human readable text.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    MalformedMessage,
+    TooManyErrors,
+    UnsupportedProtocol,
+    UnexpectedHello,
+    EncodingFailed,
+    SessionReplaced,
+    InvalidAnswer,
+    AnswerLocked,
+    SpectatorOnly,
+    Lifeline,
+    Team,
+    Chat,
+    Reaction,
+    Duel,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum AnswerStatus {
     Correct,
     Incorrect,
     NoAnswer,
+    Skipped,
+    /// An estimate within the tolerance that is not exact, with its rank
+    /// among all estimates and the points it earns.
+    Close {
+        rank: u32,
+        points: u32,
+    },
+}
+
+impl AnswerStatus {
+    pub fn points(&self) -> u32 {
+        match self {
+            AnswerStatus::Correct => MAX_POINTS,
+            AnswerStatus::Close { points, .. } => *points,
+            AnswerStatus::Incorrect | AnswerStatus::NoAnswer | AnswerStatus::Skipped => 0,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum ClientMessage {
-    Answer { answer_idx: OptionIndex },
+    Answer {
+        answer_idx: OptionIndex,
+    },
+    TextAnswer {
+        answer: String,
+    },
+    NumberAnswer {
+        answer: f64,
+    },
+    UseLifeline {
+        lifeline: Lifeline,
+    },
+    CreateTeam {
+        name: String,
+    },
+    JoinTeam {
+        name: String,
+    },
+    LeaveTeam,
+    Chat {
+        text: String,
+    },
+    MuteChat {
+        user_id: String,
+    },
+    DeleteChat {
+        id: u64,
+    },
+    React {
+        emoji: String,
+    },
+    Hello {
+        protocol_version: u32,
+        client: String,
+        /// Encodings the client can read, most preferred first.
+        #[serde(default)]
+        encodings: Vec<String>,
+    },
+    /// Asks for a `Pong` to estimate the offset between the clocks.
+    Ping {
+        client_time: u64,
+    },
+}
+
+/// Wire format of the messages on a game connection. JSON travels in text
+/// frames, the others in binary frames.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    #[serde(rename = "json")]
+    Json,
+    #[serde(rename = "msgpack")]
+    MessagePack,
+    #[serde(rename = "cbor")]
+    Cbor,
+}
+
+impl Encoding {
+    /// Looks up an encoding by the name clients use for it.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Encoding::Json),
+            "msgpack" => Some(Encoding::MessagePack),
+            "cbor" => Some(Encoding::Cbor),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TeamScore {
+    pub name: String,
+    pub members: Vec<String>,
+    pub score: u32,
+}
+
+/// How the results of a team's members are pooled into the team's score.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TeamScoring {
+    MajorityVote,
+    #[default]
+    Sum,
+    Best,
+}
+
+/// Totals over a player's practice sessions, kept apart from their
+/// competitive scores.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PracticeStats {
+    pub sessions: u32,
+    pub questions: u32,
+    pub correct: u32,
+    pub points: u32,
+}
+
+impl PracticeStats {
+    pub fn record(&mut self, statuses: &[AnswerStatus]) {
+        self.sessions += 1;
+        self.questions += statuses.len() as u32;
+        self.correct += statuses
+            .iter()
+            .filter(|status| **status == AnswerStatus::Correct)
+            .count() as u32;
+        self.points += statuses.iter().map(AnswerStatus::points).sum::<u32>();
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuelOutcome {
+    Won,
+    Lost,
+    Draw,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lifeline {
+    FiftyFifty,
+    Skip,
+    SecondChance,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AnswerStatus, Game, Media, MediaKind, OptionIndex, Question, QuestionKind,
+        QuestionValidationError, ServerMessage, DEFAULT_MAX_DISTANCE,
+    };
+
+    fn sample_question(options: usize, answer_idx: OptionIndex) -> Question {
+        Question::choice(
+            "What is the question?".into(),
+            (1..=options).map(|i| format!("Option {i}")).collect(),
+            answer_idx,
+        )
+    }
+
+    #[test]
+    fn reads_four_option_games() {
+        let game = r#"{
+            "questions": [{
+                "question": "What is the question?",
+                "options": ["Option 1", "Option 2", "Option 3", "Option 4"],
+                "answer_idx": "Four"
+            }]
+        }"#;
+
+        let game: Game = serde_json::from_str(game).unwrap();
+        assert_eq!(game.questions[0], sample_question(4, OptionIndex::Four));
+        assert!(game.validate().is_ok());
+    }
+
+    #[test]
+    fn accepts_two_to_six_options() {
+        for options in 2..=6 {
+            assert!(sample_question(options, OptionIndex::Two)
+                .validate()
+                .is_ok());
+        }
+
+        let question = Question::true_false("Is the sky blue?".into(), false);
+        assert_eq!(question.options(), ["True", "False"]);
+        assert!(question.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_option_counts() {
+        assert_eq!(
+            sample_question(1, OptionIndex::One).validate(),
+            Err(QuestionValidationError::TooFewOptions)
+        );
+        assert_eq!(
+            sample_question(7, OptionIndex::One).validate(),
+            Err(QuestionValidationError::TooManyOptions)
+        );
+    }
+
+    #[test]
+    fn rejects_answer_out_of_range() {
+        assert_eq!(
+            sample_question(3, OptionIndex::Four).validate(),
+            Err(QuestionValidationError::AnswerOutOfRange)
+        );
+    }
+
+    #[test]
+    fn reads_text_questions() {
+        let question = r#"{
+            "question": "What is the capital of Ethiopia?",
+            "accepted_answers": ["Addis Ababa", "Finfinne"]
+        }"#;
+
+        let question: Question = serde_json::from_str(question).unwrap();
+        assert_eq!(
+            question.kind,
+            QuestionKind::Text {
+                accepted_answers: vec!["Addis Ababa".into(), "Finfinne".into()],
+                max_distance: DEFAULT_MAX_DISTANCE,
+            }
+        );
+        assert!(question.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_text_questions_without_answers() {
+        assert_eq!(
+            Question::text("What is the question?".into(), vec![" ".into()]).validate(),
+            Err(QuestionValidationError::NoAcceptedAnswers)
+        );
+    }
+
+    #[test]
+    fn reads_number_questions() {
+        let question = r#"{
+            "question": "How many regions does Ethiopia have?",
+            "answer": 12,
+            "tolerance": 5
+        }"#;
+
+        let question: Question = serde_json::from_str(question).unwrap();
+        assert_eq!(
+            question.kind,
+            QuestionKind::Number {
+                answer: 12.0,
+                tolerance: 5.0,
+            }
+        );
+        assert!(question.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_non_positive_tolerance() {
+        assert_eq!(
+            Question::number("What is the question?".into(), 12.0, 0.0).validate(),
+            Err(QuestionValidationError::InvalidTolerance)
+        );
+    }
+
+    #[test]
+    fn reads_media_attachments() {
+        let question = r#"{
+            "question": "Whose voice is this?",
+            "media": { "kind": "Audio", "path": "clips/voice.mp3", "alt": "A short voice clip" },
+            "options": ["Option 1", "Option 2"],
+            "option_media": [null, { "kind": "Image", "path": "two.png", "alt": "Option 2" }],
+            "answer_idx": "One"
+        }"#;
+
+        let question: Question = serde_json::from_str(question).unwrap();
+        assert_eq!(question.all_media().len(), 2);
+        assert!(question.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_media_outside_the_media_directory() {
+        for path in [
+            "",
+            "/etc/passwd",
+            "../secret.png",
+            "images/../../secret.png",
+        ] {
+            let question =
+                Question::true_false("Is the sky blue?".into(), true).with_media(Media {
+                    kind: MediaKind::Image,
+                    path: path.into(),
+                    alt: "The sky".into(),
+                });
+
+            assert_eq!(
+                question.validate(),
+                Err(QuestionValidationError::InvalidMediaPath)
+            );
+        }
+    }
+
+    #[test]
+    fn stamps_timed_messages_without_nesting_questions() {
+        let question = Question::true_false("Is the sky blue?".into(), true);
+        let mut msg = ServerMessage::Question {
+            question: (&question).into(),
+            sent_at: 0,
+            deadline: 2_000,
+        };
+        msg.stamp(1_000);
+
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["type"], "Question");
+        assert_eq!(json["question"], "Is the sky blue?");
+        assert_eq!(json["sent_at"], 1_000);
+        assert_eq!(json["deadline"], 2_000);
+    }
+
+    #[test]
+    fn stamps_answers_and_stats() {
+        let mut answer = ServerMessage::TextAnswer {
+            status: AnswerStatus::Correct,
+            answer: "Paris".into(),
+            sent_at: 0,
+            deadline: 3_000,
+        };
+        let mut stats = ServerMessage::QuestionStats {
+            counts: vec![1, 0],
+            answered: 1,
+            correct: 1,
+            players: 2,
+            sent_at: 0,
+            deadline: 3_000,
+        };
+        answer.stamp(1_000);
+        stats.stamp(1_000);
+
+        for msg in [answer, stats] {
+            let json = serde_json::to_value(&msg).unwrap();
+            assert_eq!(
+                (json["sent_at"].clone(), json["deadline"].clone()),
+                (1_000.into(), 3_000.into())
+            );
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use crate::models::{AnswerStatus, Game, OptionIndex};
+use crate::models::{Answer, AnswerStatus, Game, Lifeline, PracticeStats};
 use async_trait::async_trait;
 use std::error::Error;
 
@@ -43,23 +43,43 @@ pub trait UsersDatabase {
 pub trait GameDatabase {
     type Error: Error + Send + Sync + 'static;
     async fn get_game(&self) -> Result<Option<Game>, Self::Error>;
-    async fn set_answer(
+    /// Sets the game played at the next start signal.
+    async fn set_game(&self, game: &Game) -> Result<(), Self::Error>;
+    /// Drops the game set for the next start signal, so it is not played
+    /// again. The database's default game, if it has one, is played instead.
+    async fn clear_game(&self) -> Result<(), Self::Error>;
+    async fn get_pack(&self, name: &str) -> Result<Option<Game>, Self::Error>;
+    async fn set_pack(&self, name: &str, game: &Game) -> Result<(), Self::Error>;
+    async fn set_answer(&self, id: &str, question: &str, answer: Answer)
+        -> Result<(), Self::Error>;
+    async fn get_answer(&self, id: &str, question: &str) -> Result<Option<Answer>, Self::Error>;
+    async fn get_answers(&self, question: &str) -> Result<Vec<Answer>, Self::Error>;
+    async fn set_answer_status(
         &self,
         id: &str,
         question: &str,
-        answer: OptionIndex,
+        answer_status: &AnswerStatus,
     ) -> Result<(), Self::Error>;
-    async fn get_answer(
+    async fn get_answer_status(
         &self,
         id: &str,
         question: &str,
-    ) -> Result<Option<OptionIndex>, Self::Error>;
-    async fn set_answer_status(
+    ) -> Result<Option<AnswerStatus>, Self::Error>;
+    async fn get_answers_statuses(&self, id: &str) -> Result<Vec<AnswerStatus>, Self::Error>;
+    async fn set_score(&self, id: &str, score: u32) -> Result<(), Self::Error>;
+    async fn add_lifeline(
         &self,
         id: &str,
         question: &str,
-        answer_status: &AnswerStatus,
+        lifeline: Lifeline,
     ) -> Result<(), Self::Error>;
-    async fn get_answers_statuses(&self, id: &str) -> Result<Vec<AnswerStatus>, Self::Error>;
-    async fn set_score(&self, id: &str, score: u32) -> Result<(), Self::Error>;
+    async fn get_lifelines(&self, id: &str) -> Result<Vec<(String, Lifeline)>, Self::Error>;
+    async fn clear_lifelines(&self, id: &str) -> Result<(), Self::Error>;
+    /// Deletes the answers, results, scores and lifelines stored for ids
+    /// that start with `prefix`.
+    async fn clear_prefixed(&self, prefix: &str) -> Result<(), Self::Error>;
+    async fn get_rating(&self, id: &str) -> Result<Option<u32>, Self::Error>;
+    async fn set_rating(&self, id: &str, rating: u32) -> Result<(), Self::Error>;
+    async fn get_practice_stats(&self, id: &str) -> Result<Option<PracticeStats>, Self::Error>;
+    async fn set_practice_stats(&self, id: &str, stats: &PracticeStats) -> Result<(), Self::Error>;
 }
@@ -0,0 +1,8 @@
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait GamePreparer {
+    type Error: std::error::Error + Send + Sync + 'static;
+    /// Gets the next game ready, right before its start signal is sent.
+    async fn prepare_game(&self) -> Result<(), Self::Error>;
+}
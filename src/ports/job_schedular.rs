@@ -4,5 +4,6 @@ use std::{error::Error, time::Duration};
 #[async_trait]
 pub trait JobSchedular {
     type Error: Error + Send + Sync + 'static;
-    async fn time_till_game(&mut self) -> Result<Duration, Self::Error>;
+    /// Returns `None` when the game has no schedule and is started by hand.
+    async fn time_till_game(&mut self) -> Result<Option<Duration>, Self::Error>;
 }
@@ -1,12 +1,16 @@
 mod database;
+mod game_preparer;
 mod game_start_notifier;
 mod hasher;
 mod id;
 mod job_schedular;
+mod question_store;
 mod token_generator;
 pub use database::*;
+pub use game_preparer::*;
 pub use game_start_notifier::*;
 pub use hasher::*;
 pub use id::*;
 pub use job_schedular::*;
+pub use question_store::*;
 pub use token_generator::*;
@@ -0,0 +1,12 @@
+use crate::models::BankQuestion;
+use async_trait::async_trait;
+use std::error::Error;
+
+#[async_trait]
+pub trait QuestionStore {
+    type Error: Error + Send + Sync + 'static;
+    async fn get_question(&self, id: &str) -> Result<Option<BankQuestion>, Self::Error>;
+    async fn get_questions(&self) -> Result<Vec<BankQuestion>, Self::Error>;
+    /// Adds the question, or replaces the one with the same id.
+    async fn set_question(&self, question: &BankQuestion) -> Result<(), Self::Error>;
+}
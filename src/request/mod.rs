@@ -1,2 +1,6 @@
+mod questions;
+mod rooms;
 mod users;
+pub use questions::*;
+pub use rooms::*;
 pub use users::*;
@@ -0,0 +1,101 @@
+use crate::models::{Difficulty, PackFormat, Question, QuestionValidationError};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A question to add to the question bank, or the new contents of one.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct QuestionRequest {
+    #[serde(flatten)]
+    question: Question,
+    category: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    difficulty: Difficulty,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+}
+
+impl QuestionRequest {
+    pub fn new(question: Question, category: String, difficulty: Difficulty) -> Self {
+        Self {
+            question,
+            category,
+            tags: Vec::new(),
+            difficulty,
+            source: None,
+        }
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_source(mut self, source: String) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn question(&self) -> &Question {
+        &self.question
+    }
+
+    pub fn category(&self) -> &str {
+        &self.category
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum QuestionRequestValidationError {
+    #[error("category is empty")]
+    CategoryEmpty,
+    #[error("invalid question: {0}")]
+    InvalidQuestion(#[from] QuestionValidationError),
+}
+
+impl QuestionRequest {
+    pub fn validate(&self) -> Result<(), QuestionRequestValidationError> {
+        use QuestionRequestValidationError::*;
+
+        if self.category.trim().is_empty() {
+            return Err(CategoryEmpty);
+        }
+
+        self.question.validate()?;
+        Ok(())
+    }
+}
+
+/// Filters for searching the question bank. Every filter that is set has to
+/// match.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct QuestionSearchRequest {
+    pub category: Option<String>,
+    pub tag: Option<String>,
+    pub difficulty: Option<Difficulty>,
+    pub text: Option<String>,
+    #[serde(default)]
+    pub include_retired: bool,
+}
+
+/// How a question pack is read or written. Dry runs check a pack without
+/// importing it.
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+pub struct QuestionPackRequest {
+    #[serde(default)]
+    pub format: PackFormat,
+    #[serde(default)]
+    pub dry_run: bool,
+}
@@ -0,0 +1,52 @@
+use crate::models::TeamScoring;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct CreateRoomRequest {
+    pack: String,
+    #[serde(default)]
+    team_scoring: TeamScoring,
+    #[serde(default)]
+    shuffle: bool,
+}
+
+impl CreateRoomRequest {
+    pub fn new(pack: String) -> Self {
+        Self {
+            pack,
+            team_scoring: TeamScoring::default(),
+            shuffle: false,
+        }
+    }
+
+    pub fn pack(&self) -> &str {
+        &self.pack
+    }
+
+    pub fn team_scoring(&self) -> TeamScoring {
+        self.team_scoring
+    }
+
+    pub fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CreateRoomValidationError {
+    #[error("pack is empty")]
+    PackEmpty,
+}
+
+impl CreateRoomRequest {
+    pub fn validate(&self) -> Result<(), CreateRoomValidationError> {
+        use CreateRoomValidationError::*;
+
+        if self.pack.trim().is_empty() {
+            return Err(PackEmpty);
+        }
+
+        Ok(())
+    }
+}
@@ -98,3 +98,11 @@ impl From<RegisterRequest> for LoginRequest {
         Self::new(request.username, request.password)
     }
 }
+
+/// The login a websocket passes in its query string, as browsers cannot set
+/// headers on the upgrade request.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct TokenRequest {
+    #[serde(default)]
+    pub token: Option<String>,
+}
@@ -12,7 +12,7 @@ type Message = {
 } | {
   type: "Answer",
   status: string,
-  answer_idx: "One" | "Two" | "Three" | "Four"
+  answer_idx: "One" | "Two" | "Three" | "Four" | "Five" | "Six"
 } | {
   type: "NoGame"
 } | {